@@ -0,0 +1,46 @@
+//! Wall-clock timings for the interpreter. Run with `cargo bench`.
+//!
+//! No benchmarking crate is needed: each case runs a few times and the fastest
+//! time is printed. Parsing is left out of the timings.
+
+use std::hint::black_box;
+use std::time::{Duration, Instant};
+
+use velo::models::{Cosmos, Vessel};
+use velo::sail::{Config, run_headless, sail};
+
+const RUNS: usize = 5;
+
+/// Runs `case` a few times and prints the fastest time it reports under `name`.
+fn measure(name: &str, mut case: impl FnMut() -> Duration) -> Duration {
+    let fastest = (0..RUNS).map(|_| case()).min().unwrap_or_default();
+    println!("{:<48} {:>12.2?}", name, fastest);
+    fastest
+}
+
+/// A single row of ten million Void runes, crossed once.
+fn sparse_row(skip_void: bool) -> Duration {
+    let source = format!(">{}", " ".repeat(10_000_000));
+    let cosmos = Cosmos::parse(&source).expect("the program parses");
+    let vessel = Vessel::new(0, 0, cosmos.get(0, 0));
+    let config = Config::new(false, false, false).with_skip_void(skip_void);
+    let started = Instant::now();
+    black_box(sail(cosmos, vessel, config).expect("the program starts"));
+    started.elapsed()
+}
+
+/// A single row of ten million Entropy Charge runes, run headless.
+fn dense_row() -> Duration {
+    let source = format!(">{}", "+".repeat(10_000_000));
+    let cosmos = Cosmos::parse(&source).expect("the program parses");
+    let vessel = Vessel::new(0, 0, cosmos.get(0, 0));
+    let started = Instant::now();
+    black_box(run_headless(cosmos, vessel).expect("the program starts"));
+    started.elapsed()
+}
+
+fn main() {
+    measure("sparse row, one cycle per Void", || sparse_row(false));
+    measure("sparse row, --no-exec-on-void", || sparse_row(true));
+    measure("dense row, run_headless", dense_row);
+}
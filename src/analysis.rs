@@ -0,0 +1,448 @@
+use std::collections::HashSet;
+use std::fmt;
+
+use crate::models::{Cosmos, Direction, Rotation, Rune};
+
+/// Authoring mistakes `Cosmos::validated` finds before a program is run.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ValidationError {
+    Empty,                // The grid has no cells.
+    NotThrustStart(Rune), // The top left Rune gives the Vessel no initial velocity.
+    NoHalt,               // No Rune besides the start can stop the Vessel.
+}
+
+impl fmt::Display for ValidationError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Empty => write!(f, "The cosmos is empty."),
+            Self::NotThrustStart(rune) => write!(
+                f,
+                "The top left rune is {}, not a Thrust rune, so the vessel cannot start.",
+                rune.name()
+            ),
+            Self::NoHalt => write!(
+                f,
+                "No rune besides the start can stop the vessel; it can only leave the cosmos."
+            ),
+        }
+    }
+}
+
+impl std::error::Error for ValidationError {}
+
+/// The Runes that direct the Vessel, joined by the straight runs between them;
+/// see `Cosmos::control_flow_graph`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ControlFlowGraph {
+    // Reachable flow Runes in row order, the start first.
+    nodes: Vec<(usize, usize, Rune)>,
+    // From a node, leaving in a heading, to the next node, or `None` off the edge.
+    edges: Vec<FlowEdge>,
+}
+
+/// A straight run of the Vessel between two nodes of a `ControlFlowGraph`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FlowEdge {
+    pub from: (usize, usize),
+    pub direction: Direction,
+    // `None` when the Vessel leaves the Cosmos before reaching another node.
+    pub to: Option<(usize, usize)>,
+}
+
+impl ControlFlowGraph {
+    pub fn nodes(&self) -> &[(usize, usize, Rune)] {
+        &self.nodes
+    }
+
+    pub fn edges(&self) -> &[FlowEdge] {
+        &self.edges
+    }
+
+    /// Renders the graph in Graphviz DOT format, with nodes named `x,y` and
+    /// edges labelled with the heading.
+    pub fn to_dot(&self) -> String {
+        let mut dot = String::from("digraph velo {\n");
+        for &(x, y, rune) in &self.nodes {
+            let glyph = rune.to_char().to_string();
+            dot.push_str(&format!(
+                "    \"{},{}\" [label=\"{} ({}, {})\"];\n",
+                x,
+                y,
+                glyph.replace('\\', "\\\\").replace('"', "\\\""),
+                x,
+                y
+            ));
+        }
+        if self.edges.iter().any(|edge| edge.to.is_none()) {
+            dot.push_str("    \"out\" [label=\"out of the cosmos\", shape=plaintext];\n");
+        }
+        for edge in &self.edges {
+            let to = match edge.to {
+                Some((x, y)) => format!("{},{}", x, y),
+                None => "out".to_string(),
+            };
+            dot.push_str(&format!(
+                "    \"{},{}\" -> \"{}\" [label=\"{}\"];\n",
+                edge.from.0,
+                edge.from.1,
+                to,
+                edge.direction.short()
+            ));
+        }
+        dot.push_str("}\n");
+        dot
+    }
+}
+
+impl Cosmos {
+    /// Checks the grid for common mistakes before running it.
+    ///
+    /// The grid must not be empty, the top left Rune must be a Thrust rune,
+    /// and some other Rune must be able to halt the program: a Thrust rune
+    /// (which can slow the Vessel to 0) including `?`, or `{`, `Q`, `F` or `Z`.
+    /// Whether that Rune is actually reached is not checked.
+    pub fn validated(self) -> Result<Cosmos, ValidationError> {
+        if self.is_empty() {
+            return Err(ValidationError::Empty);
+        }
+
+        let start = self.get(0, 0);
+        if !matches!(
+            start,
+            Rune::ThrustUp | Rune::ThrustDown | Rune::ThrustLeft | Rune::ThrustRight
+        ) {
+            return Err(ValidationError::NotThrustStart(start));
+        }
+
+        let can_halt = (0..self.height())
+            .flat_map(|y| (0..self.width()).map(move |x| (x, y)))
+            .filter(|&coordinate| coordinate != (0, 0))
+            .any(|(x, y)| may_halt(self.get(x, y)));
+        if !can_halt {
+            return Err(ValidationError::NoHalt);
+        }
+
+        Ok(self)
+    }
+
+    /// Computes every coordinate the Vessel could possibly visit when leaving
+    /// `(x, y)` in `start_direction`.
+    ///
+    /// This is a conservative over-approximation: data-dependent Runes (the
+    /// Steer and random Runes) are treated as branching into every heading they
+    /// could produce, and velocity is never assumed to reach zero. Coordinates
+    /// outside the returned set are dead code. The `R` and `G` Runes, which
+    /// rotate the grid and jump to labels at run time, are not modelled.
+    pub fn reachable_from(
+        &self,
+        x: usize,
+        y: usize,
+        start_direction: Direction,
+    ) -> HashSet<(usize, usize)> {
+        let mut reachable = HashSet::new();
+        let mut visited = HashSet::new();
+        let mut pending = vec![(x, y, start_direction)];
+
+        reachable.insert((x, y));
+
+        while let Some((x, y, direction)) = pending.pop() {
+            if !visited.insert((x, y, direction)) {
+                continue;
+            }
+
+            let Some((next_x, next_y)) = self.neighbour(x, y, direction) else {
+                continue;
+            };
+
+            reachable.insert((next_x, next_y));
+
+            let rune = self.get(next_x, next_y);
+            for heading in possible_headings(rune, direction) {
+                pending.push((next_x, next_y, heading));
+
+                if may_skip(rune) {
+                    // The Vessel may jump over the following Rune without impacting it.
+                    if let Some((skipped_x, skipped_y)) = self.neighbour(next_x, next_y, heading) {
+                        pending.push((skipped_x, skipped_y, heading));
+                    }
+                }
+            }
+        }
+
+        reachable
+    }
+
+    /// Builds the control-flow graph of the program: its nodes are the start
+    /// and every reachable Rune that can turn, skip or halt the Vessel, and
+    /// its edges the straight runs between them.
+    ///
+    /// Like `reachable_from`, data-dependent Runes branch into every heading
+    /// they could produce, and the `R` and `G` Runes are not modelled.
+    pub fn control_flow_graph(&self) -> ControlFlowGraph {
+        if self.is_empty() {
+            return ControlFlowGraph {
+                nodes: Vec::new(),
+                edges: Vec::new(),
+            };
+        }
+
+        let start_direction = match self.get(0, 0) {
+            Rune::ThrustUp => Direction::Up,
+            Rune::ThrustDown => Direction::Down,
+            Rune::ThrustLeft => Direction::Left,
+            Rune::ThrustRight => Direction::Right,
+            _ => Direction::None,
+        };
+
+        let mut nodes = HashSet::from([(0, 0)]);
+        let mut edges = Vec::new();
+        let mut visited = HashSet::new();
+        let mut pending = vec![(0, 0, start_direction)];
+
+        while let Some((x, y, direction)) = pending.pop() {
+            if direction == Direction::None || !visited.insert((x, y, direction)) {
+                continue;
+            }
+
+            let mut targets = vec![self.next_node(x, y, direction)];
+            if may_skip(self.get(x, y))
+                && let Some((skipped_x, skipped_y)) = self.neighbour(x, y, direction)
+            {
+                targets.push(self.next_node(skipped_x, skipped_y, direction));
+            }
+            targets.dedup();
+
+            for to in targets {
+                edges.push(FlowEdge {
+                    from: (x, y),
+                    direction,
+                    to,
+                });
+                if let Some((to_x, to_y)) = to {
+                    nodes.insert((to_x, to_y));
+                    for heading in possible_headings(self.get(to_x, to_y), direction) {
+                        pending.push((to_x, to_y, heading));
+                    }
+                }
+            }
+        }
+
+        let mut nodes: Vec<(usize, usize, Rune)> = nodes
+            .into_iter()
+            .map(|(x, y)| (x, y, self.get(x, y)))
+            .collect();
+        nodes.sort_by_key(|&(x, y, _)| (y, x));
+        edges.sort_by_key(|edge| (edge.from.1, edge.from.0, edge.direction.short(), edge.to));
+
+        ControlFlowGraph { nodes, edges }
+    }
+
+    /// The first flow Rune met travelling from `(x, y)` in `direction`, or
+    /// `None` if the Vessel leaves the Cosmos first.
+    fn next_node(
+        &self,
+        mut x: usize,
+        mut y: usize,
+        direction: Direction,
+    ) -> Option<(usize, usize)> {
+        loop {
+            (x, y) = self.neighbour(x, y, direction)?;
+            if is_flow_rune(self.get(x, y)) {
+                return Some((x, y));
+            }
+        }
+    }
+
+    /// The in-bounds coordinate one step from `(x, y)` in `direction`, if any.
+    fn neighbour(&self, x: usize, y: usize, direction: Direction) -> Option<(usize, usize)> {
+        if direction == Direction::None {
+            return None;
+        }
+
+        let (dx, dy) = direction.delta();
+        let next_x = x.checked_add_signed(dx as isize)?;
+        let next_y = y.checked_add_signed(dy as isize)?;
+
+        if next_x < self.width() && next_y < self.height() {
+            Some((next_x, next_y))
+        } else {
+            None
+        }
+    }
+}
+
+/// Every heading the Vessel may leave `rune` with after arriving in `direction`.
+fn possible_headings(rune: Rune, direction: Direction) -> Vec<Direction> {
+    let thrust = |rune_direction: Direction| {
+        if rune_direction == direction || rune_direction.opposite_to(direction) {
+            direction
+        } else {
+            rune_direction
+        }
+    };
+
+    match rune {
+        Rune::ThrustUp => vec![thrust(Direction::Up)],
+        Rune::ThrustDown => vec![thrust(Direction::Down)],
+        Rune::ThrustLeft => vec![thrust(Direction::Left)],
+        Rune::ThrustRight => vec![thrust(Direction::Right)],
+        Rune::SteerByValue | Rune::RestoreDir => vec![
+            direction,
+            direction.rotate(Rotation::Right),
+            direction.rotate(Rotation::UTurn),
+            direction.rotate(Rotation::Left),
+        ],
+        Rune::ThrustRandom => vec![
+            direction,
+            direction.rotate(Rotation::Left),
+            direction.rotate(Rotation::Right),
+        ],
+        Rune::SteerLeft => vec![direction, direction.rotate(Rotation::Left)],
+        Rune::SteerRight | Rune::SteerByAhead | Rune::Spawn => {
+            vec![direction, direction.rotate(Rotation::Right)]
+        }
+        Rune::ReverseIfZero => vec![direction, direction.rotate(Rotation::UTurn)],
+        _ => vec![direction],
+    }
+}
+
+/// Whether `rune` can change where the Vessel goes next.
+fn is_flow_rune(rune: Rune) -> bool {
+    may_halt(rune)
+        || may_skip(rune)
+        || [
+            Direction::Up,
+            Direction::Down,
+            Direction::Left,
+            Direction::Right,
+        ]
+        .into_iter()
+        .any(|direction| possible_headings(rune, direction) != [direction])
+}
+
+/// Whether `rune` can make the Vessel jump over the following Rune.
+fn may_skip(rune: Rune) -> bool {
+    matches!(rune, Rune::SkipIfLess | Rune::InputAndSkipIfZero)
+}
+
+/// Whether `rune` can end the program without the Vessel leaving the Cosmos.
+fn may_halt(rune: Rune) -> bool {
+    matches!(
+        rune,
+        Rune::ThrustUp
+            | Rune::ThrustDown
+            | Rune::ThrustLeft
+            | Rune::ThrustRight
+            | Rune::ThrustRandom
+            | Rune::RetreatByCell
+            | Rune::Exit
+            | Rune::HaltIfFast
+            | Rune::StopIfZero
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reachable_from_follows_turns_and_skips_dead_code() {
+        let cosmos = Cosmos::parse(">+v.\n.. ").unwrap();
+        let reachable = cosmos.reachable_from(0, 0, Direction::Right);
+        let expected = HashSet::from([(0, 0), (1, 0), (2, 0), (2, 1)]);
+        assert_eq!(reachable, expected);
+    }
+
+    #[test]
+    fn reachable_from_branches_at_steer_runes() {
+        let cosmos = Cosmos::parse(" + \n>[ \n + ").unwrap();
+        let reachable = cosmos.reachable_from(0, 1, Direction::Right);
+        assert!(reachable.contains(&(1, 0)));
+        assert!(reachable.contains(&(2, 1)));
+        assert!(!reachable.contains(&(1, 2)));
+    }
+
+    #[test]
+    fn reachable_from_includes_the_rune_after_a_skip() {
+        // `L` may jump over the `v`, so the `+` behind it is live.
+        let reachable = Cosmos::parse(">Lv+")
+            .unwrap()
+            .reachable_from(0, 0, Direction::Right);
+        assert!(reachable.contains(&(3, 0)));
+    }
+
+    #[test]
+    fn reachable_from_follows_both_headings_of_a_u_turn() {
+        // Only the Vessel turned back at `U` reaches the `+`.
+        let reachable = Cosmos::parse("+ >U")
+            .unwrap()
+            .reachable_from(2, 0, Direction::Right);
+        assert!(reachable.contains(&(0, 0)));
+    }
+
+    #[test]
+    fn validated_rejects_programs_that_cannot_start_or_halt() {
+        let check = |source: &str| Cosmos::parse(source).unwrap().validated().err();
+        assert_eq!(check(">+<"), None);
+        assert_eq!(check(">+Q"), None);
+        assert_eq!(check(">+."), Some(ValidationError::NoHalt));
+        assert_eq!(
+            check("P+<"),
+            Some(ValidationError::NotThrustStart(Rune::Parking))
+        );
+        assert_eq!(check(""), Some(ValidationError::Empty));
+    }
+
+    #[test]
+    fn validated_accepts_every_halting_rune() {
+        for halt in ["<", "?", "{", "Q", "F", "Z"] {
+            let cosmos = Cosmos::parse(&format!(">+{}", halt)).unwrap();
+            assert!(cosmos.validated().is_ok(), "{}", halt);
+        }
+    }
+
+    #[test]
+    fn control_flow_graph_joins_the_turns_of_the_program() {
+        let graph = Cosmos::parse(">+v\n  <").unwrap().control_flow_graph();
+        let nodes = [
+            (0, 0, Rune::ThrustRight),
+            (2, 0, Rune::ThrustDown),
+            (2, 1, Rune::ThrustLeft),
+        ];
+        assert_eq!(graph.nodes(), nodes);
+        let edges = [
+            FlowEdge {
+                from: (0, 0),
+                direction: Direction::Right,
+                to: Some((2, 0)),
+            },
+            FlowEdge {
+                from: (2, 0),
+                direction: Direction::Down,
+                to: Some((2, 1)),
+            },
+            FlowEdge {
+                from: (2, 1),
+                direction: Direction::Left,
+                to: None,
+            },
+        ];
+        assert_eq!(graph.edges(), edges);
+
+        let dot = graph.to_dot();
+        assert!(dot.starts_with("digraph velo {\n"));
+        assert!(dot.contains("    \"2,0\" [label=\"v (2, 0)\"];\n"));
+        assert!(dot.contains("    \"2,1\" -> \"out\""));
+        assert!(dot.contains("\"out\" [label=\"out of the cosmos\""));
+    }
+
+    #[test]
+    fn blank_lines_make_an_empty_cosmos() {
+        let cosmos = Cosmos::parse("\n\n\n").unwrap();
+        assert!(cosmos.is_empty());
+        assert_eq!(cosmos.get(0, 0), Rune::Void);
+        assert_eq!(cosmos.get(5, 2), Rune::Void);
+        assert!(cosmos.control_flow_graph().nodes().is_empty());
+        assert_eq!(cosmos.validated().err(), Some(ValidationError::Empty));
+    }
+}
@@ -0,0 +1,268 @@
+use std::collections::VecDeque;
+use std::fmt;
+use std::io::{self, BufRead, BufReader, BufWriter, Write};
+
+use crate::models::Rune;
+use crate::random::Rng;
+use crate::sail::{FlushPolicy, OutputEncoding};
+
+/// Runtime resources shared by all Runes during a single run.
+pub struct ExecutionContext {
+    rng: Rng,
+    // Where program input comes from; stdin unless replaced.
+    input: Box<dyn BufRead>,
+    // Input handed over by the host, read instead of `input` once fed.
+    queue: Option<InputQueue>,
+    // Where program output goes; buffered stdout unless replaced.
+    output: Box<dyn Write>,
+    // Where trace and Debug rune lines go; stdout unless replaced.
+    trace: Box<dyn Write>,
+    // Where warnings go; stderr unless replaced.
+    diagnostics: Box<dyn Write>,
+    // Entries printed by the OutputString rune, indexed by cell value.
+    strings: Vec<String>,
+    // How the Output rune turns a cell into bytes.
+    output_encoding: OutputEncoding,
+    // The cycle being executed, as counted by the Machine.
+    step: u64,
+    // When buffered program output is flushed, besides before input and at the end.
+    flush_policy: FlushPolicy,
+    // Bytes written by the output Runes so far.
+    output_bytes: u64,
+    // Most bytes the program may write, from `Config::with_output_limit`.
+    output_limit: Option<u64>,
+    // Bytes written during the current step, handed to the host by
+    // `Machine::step`. Only collected while that asks for them.
+    step_output: Option<Vec<u8>>,
+}
+
+impl ExecutionContext {
+    /// Creates a context. Without a seed, the RNG is seeded from OS entropy.
+    pub fn new(seed: Option<u64>) -> Self {
+        let rng = match seed {
+            Some(seed) => Rng::from_seed(seed),
+            None => Rng::from_entropy(),
+        };
+        Self::with_rng(rng)
+    }
+
+    pub(crate) fn with_rng(rng: Rng) -> Self {
+        Self::restore(rng, 0)
+    }
+
+    /// Rebuilds a context from previously captured state.
+    pub(crate) fn restore(rng: Rng, output_bytes: u64) -> Self {
+        Self {
+            rng,
+            // Not `stdin().lock()`: that lock is held until the context is dropped,
+            // so a second Machine on the same thread would deadlock.
+            input: Box::new(BufReader::new(io::stdin())),
+            queue: None,
+            // Buffered, so output-heavy programs do not lock stdout per byte.
+            output: Box::new(BufWriter::new(io::stdout())),
+            trace: Box::new(io::stdout()),
+            diagnostics: Box::new(io::stderr()),
+            strings: Vec::new(),
+            output_encoding: OutputEncoding::Utf8,
+            flush_policy: FlushPolicy::OnTermination,
+            step: 0,
+            output_bytes,
+            output_limit: None,
+            step_output: None,
+        }
+    }
+
+    pub(crate) fn rng_state(&self) -> u64 {
+        self.rng.state()
+    }
+
+    pub fn rng(&mut self) -> &mut Rng {
+        &mut self.rng
+    }
+
+    /// Replaces the source of program input, dropping any queued input.
+    pub fn set_input(&mut self, input: impl BufRead + 'static) {
+        self.input = Box::new(input);
+        self.queue = None;
+    }
+
+    /// The input queue the host feeds, replacing the input reader on first use.
+    pub(crate) fn input_queue(&mut self) -> &mut InputQueue {
+        self.queue.get_or_insert_with(InputQueue::default)
+    }
+
+    /// Whether `rune` can run without waiting for input that has not been fed
+    /// yet. Always true unless the input comes from the queue.
+    pub(crate) fn input_ready(&self, rune: Rune) -> bool {
+        self.queue
+            .as_ref()
+            .is_none_or(|queue| queue.satisfies(rune))
+    }
+
+    /// The input reader. Pending output is flushed first, so prompts appear
+    /// before the program waits for input.
+    pub fn input(&mut self) -> &mut dyn BufRead {
+        self.flush_output();
+        match &mut self.queue {
+            Some(queue) => &mut queue.bytes,
+            None => &mut *self.input,
+        }
+    }
+
+    /// Reads one byte of input. Returns `None` on EOF or a read error.
+    pub fn read_byte(&mut self) -> Option<u8> {
+        let mut buffer = [0; 1];
+        self.input().read_exact(&mut buffer).ok()?;
+        Some(buffer[0])
+    }
+
+    /// Whether at least one more byte of input is available, without consuming it.
+    pub fn has_input(&mut self) -> bool {
+        matches!(self.input().fill_buf(), Ok(buffer) if !buffer.is_empty())
+    }
+
+    /// Replaces the destination of program output.
+    pub fn set_output(&mut self, output: impl Write + 'static) {
+        self.output = Box::new(output);
+    }
+
+    /// Replaces the string table used by the OutputString rune.
+    pub fn set_string_table(&mut self, strings: Vec<String>) {
+        self.strings = strings;
+    }
+
+    pub fn string_table(&self) -> &[String] {
+        &self.strings
+    }
+
+    /// The string table entry at `index`, if there is one.
+    pub fn string_entry(&self, index: usize) -> Option<&str> {
+        self.strings.get(index).map(String::as_str)
+    }
+
+    pub fn output_encoding(&self) -> OutputEncoding {
+        self.output_encoding
+    }
+
+    pub(crate) fn set_output_encoding(&mut self, encoding: OutputEncoding) {
+        self.output_encoding = encoding;
+    }
+
+    pub(crate) fn set_flush_policy(&mut self, flush_policy: FlushPolicy) {
+        self.flush_policy = flush_policy;
+    }
+
+    /// Writes program output, keeping count of the bytes written.
+    pub fn write_output(&mut self, bytes: &[u8]) {
+        let _ = self.output.write_all(bytes);
+        match self.flush_policy {
+            FlushPolicy::EveryByte => self.flush_output(),
+            FlushPolicy::OnNewline if bytes.contains(&b'\n') => self.flush_output(),
+            _ => (),
+        }
+        self.output_bytes += bytes.len() as u64;
+        if let Some(step_output) = &mut self.step_output {
+            step_output.extend_from_slice(bytes);
+        }
+    }
+
+    /// Starts collecting the bytes written, until `take_step_output`.
+    pub(crate) fn collect_step_output(&mut self) {
+        self.step_output = Some(Vec::new());
+    }
+
+    /// Takes the bytes written since `collect_step_output`, and stops collecting.
+    pub(crate) fn take_step_output(&mut self) -> Vec<u8> {
+        self.step_output.take().unwrap_or_default()
+    }
+
+    pub(crate) fn flush_output(&mut self) {
+        let _ = self.output.flush();
+    }
+
+    /// Replaces the destination of trace lines.
+    pub fn set_trace_output(&mut self, trace: impl Write + 'static) {
+        self.trace = Box::new(trace);
+    }
+
+    /// Writes one trace line.
+    pub(crate) fn trace_line(&mut self, line: fmt::Arguments) {
+        let _ = writeln!(self.trace, "{}", line);
+    }
+
+    /// Replaces the destination of warnings, such as a value the Output rune
+    /// cannot encode. Embedders can forward them to their own logger.
+    pub fn set_diagnostic_output(&mut self, diagnostics: impl Write + 'static) {
+        self.diagnostics = Box::new(diagnostics);
+    }
+
+    /// Writes one warning.
+    pub(crate) fn warn(&mut self, message: fmt::Arguments) {
+        let _ = writeln!(self.diagnostics, "Velo Warning: {}", message);
+    }
+
+    pub(crate) fn flush_trace(&mut self) {
+        let _ = self.trace.flush();
+    }
+
+    /// The number of the cycle being executed, starting at 1.
+    pub fn step(&self) -> u64 {
+        self.step
+    }
+
+    pub(crate) fn set_step(&mut self, step: u64) {
+        self.step = step;
+    }
+
+    pub fn output_bytes(&self) -> u64 {
+        self.output_bytes
+    }
+
+    pub(crate) fn set_output_limit(&mut self, limit: Option<u64>) {
+        self.output_limit = limit;
+    }
+
+    /// Whether more bytes than the output limit have been written.
+    pub fn output_limit_reached(&self) -> bool {
+        self.output_limit
+            .is_some_and(|limit| self.output_bytes > limit)
+    }
+}
+
+/// Input fed piece by piece by a host that must not block, for `Machine::poll_step`.
+#[derive(Debug, Default)]
+pub(crate) struct InputQueue {
+    bytes: VecDeque<u8>,
+    // Set once no more input will be fed; reads past the queue then see EOF.
+    closed: bool,
+}
+
+impl InputQueue {
+    pub(crate) fn feed(&mut self, bytes: &[u8]) {
+        self.bytes.extend(bytes);
+    }
+
+    pub(crate) fn close(&mut self) {
+        self.closed = true;
+    }
+
+    /// Whether the queued bytes give `rune` everything it reads, as blocking
+    /// input would once that much had been typed.
+    fn satisfies(&self, rune: Rune) -> bool {
+        if self.closed {
+            return true;
+        }
+        let bytes = &self.bytes;
+        match rune {
+            Rune::Input | Rune::InputAndSkipIfZero | Rune::InputAvailable => !bytes.is_empty(),
+            Rune::InputLength | Rune::FlushInput => bytes.contains(&b'\n'),
+            // A number is only complete once whitespace follows it.
+            Rune::InputNumber => bytes
+                .iter()
+                .skip_while(|byte| byte.is_ascii_whitespace())
+                .any(u8::is_ascii_whitespace),
+            Rune::SlurpInput => false,
+            _ => true,
+        }
+    }
+}
@@ -0,0 +1,199 @@
+use std::collections::HashSet;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// Includes nested more than this many levels deep are refused, even without a cycle.
+pub const MAX_INCLUDE_DEPTH: usize = 16;
+
+// A line starting with this names a file whose lines replace it.
+const DIRECTIVE: &str = "!include ";
+
+/// Reasons the `!include` directives of a source file cannot be expanded.
+#[derive(Debug)]
+pub enum IncludeError {
+    // An included file could not be read.
+    Io { path: PathBuf, error: io::Error },
+    // A file includes itself, directly or through other files.
+    Cycle { path: PathBuf },
+    // Includes nest deeper than `MAX_INCLUDE_DEPTH`.
+    TooDeep { path: PathBuf },
+}
+
+impl fmt::Display for IncludeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Io { path, error } => write!(f, "{}: {}", path.display(), error),
+            Self::Cycle { path } => write!(f, "{} includes itself.", path.display()),
+            Self::TooDeep { path } => write!(
+                f,
+                "{} is included more than {} levels deep.",
+                path.display(),
+                MAX_INCLUDE_DEPTH
+            ),
+        }
+    }
+}
+
+impl std::error::Error for IncludeError {}
+
+/// Reads a source file. Unlike `fs::read_to_string`, invalid UTF-8 is
+/// reported with the byte offset where it starts.
+pub fn read_source(path: &Path) -> io::Result<String> {
+    let bytes = fs::read(path)?;
+
+    String::from_utf8(bytes).map_err(|error| {
+        io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!(
+                "source is not valid UTF-8 at byte {}",
+                error.utf8_error().valid_up_to()
+            ),
+        )
+    })
+}
+
+/// The path named by `line`, if it is an `!include` directive.
+pub fn include_target(line: &str) -> Option<&str> {
+    line.strip_prefix(DIRECTIVE).map(str::trim)
+}
+
+/// Replaces every `!include path` line of `source`, which was read from
+/// `path`, with the lines of the named file, recursively. Relative paths are
+/// resolved against the directory of the including file.
+///
+/// A file may be included more than once, but not from within itself.
+pub fn expand_includes(source: &str, path: &Path) -> Result<String, IncludeError> {
+    let mut active = HashSet::new();
+    let mut expanded = String::with_capacity(source.len());
+    expand_into(source, path, 0, &mut active, &mut expanded)?;
+    Ok(expanded)
+}
+
+/// Appends the expanded lines of `source` to `expanded`. `active` holds the
+/// files being expanded further up, which `path` must not be one of.
+fn expand_into(
+    source: &str,
+    path: &Path,
+    depth: usize,
+    active: &mut HashSet<PathBuf>,
+    expanded: &mut String,
+) -> Result<(), IncludeError> {
+    if depth > MAX_INCLUDE_DEPTH {
+        return Err(IncludeError::TooDeep {
+            path: path.to_path_buf(),
+        });
+    }
+    // The same file can be reached through different relative paths.
+    let key = fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf());
+    if !active.insert(key.clone()) {
+        return Err(IncludeError::Cycle {
+            path: path.to_path_buf(),
+        });
+    }
+
+    let directory = path.parent().unwrap_or(Path::new(""));
+    for line in source.lines() {
+        match include_target(line) {
+            Some(target) => {
+                let included = directory.join(target);
+                let source = read_source(&included).map_err(|error| IncludeError::Io {
+                    path: included.clone(),
+                    error,
+                })?;
+                expand_into(&source, &included, depth + 1, active, expanded)?;
+            }
+            None => {
+                expanded.push_str(line);
+                expanded.push('\n');
+            }
+        }
+    }
+
+    active.remove(&key);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A directory in the temporary directory that is removed when dropped.
+    struct TempDir(PathBuf);
+
+    impl TempDir {
+        fn new(name: &str) -> Self {
+            let path = std::env::temp_dir().join(format!("velo-{}-{}", std::process::id(), name));
+            fs::create_dir_all(&path).unwrap();
+            Self(path)
+        }
+
+        /// Writes `contents` to the file `name` in the directory and returns its path.
+        fn file(&self, name: &str, contents: &[u8]) -> PathBuf {
+            let path = self.0.join(name);
+            fs::write(&path, contents).unwrap();
+            path
+        }
+    }
+
+    impl Drop for TempDir {
+        fn drop(&mut self) {
+            let _ = fs::remove_dir_all(&self.0);
+        }
+    }
+
+    #[test]
+    fn invalid_utf8_is_reported_with_its_offset() {
+        let dir = TempDir::new("utf8");
+        let path = dir.file("latin1.velo", b">+.\xe9");
+        let error = read_source(&path).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+        assert_eq!(error.to_string(), "source is not valid UTF-8 at byte 3");
+
+        let path = dir.file("utf8.velo", ">+. # é".as_bytes());
+        assert_eq!(read_source(&path).unwrap(), ">+. # é");
+    }
+
+    #[test]
+    fn includes_are_expanded_relative_to_the_including_file() {
+        let dir = TempDir::new("expand");
+        fs::create_dir_all(dir.0.join("lib")).unwrap();
+        dir.file("lib/tail.velo", b"  <\n");
+        dir.file("lib/body.velo", b"!include tail.velo\n");
+        let main = dir.file(
+            "main.velo",
+            b">+v\n!include lib/body.velo\n!include lib/tail.velo\n",
+        );
+        let source = read_source(&main).unwrap();
+        assert_eq!(expand_includes(&source, &main).unwrap(), ">+v\n  <\n  <\n");
+    }
+
+    #[test]
+    fn include_cycles_and_deep_nesting_are_refused() {
+        let dir = TempDir::new("cycle");
+        let a = dir.file("a.velo", b"!include b.velo\n");
+        dir.file("b.velo", b"!include ./a.velo\n");
+        let error = expand_includes("!include b.velo", &a).unwrap_err();
+        assert!(matches!(error, IncludeError::Cycle { .. }), "{}", error);
+
+        for level in 0..MAX_INCLUDE_DEPTH {
+            dir.file(
+                &format!("{}.velo", level),
+                format!("!include {}.velo\n", level + 1).as_bytes(),
+            );
+        }
+        let last = dir.file(&format!("{}.velo", MAX_INCLUDE_DEPTH), b">\n");
+        let root = dir.file("root.velo", b"");
+        assert_eq!(expand_includes("!include 1.velo", &root).unwrap(), ">\n");
+        let error = expand_includes("!include 0.velo", &root).unwrap_err();
+        assert!(
+            matches!(error, IncludeError::TooDeep { ref path } if *path == last),
+            "{}",
+            error
+        );
+
+        let missing = expand_includes("!include nowhere.velo", &a).unwrap_err();
+        assert!(matches!(missing, IncludeError::Io { .. }));
+    }
+}
@@ -1,2 +1,12 @@
+pub mod analysis;
+pub mod context;
+pub mod include;
+pub mod machine;
 pub mod models;
+pub mod parse;
+pub mod random;
 pub mod sail;
+pub mod svg;
+
+#[cfg(test)]
+mod testing;
@@ -0,0 +1,2188 @@
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::fmt;
+use std::io::{self, BufRead, Read, Write};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::context::ExecutionContext;
+use crate::models::{Cosmos, Direction, Impact, Rotation, Rune, Vessel};
+use crate::random::Rng;
+use crate::sail::{
+    CompletionReport, Config, EdgePolicy, FlushPolicy, OutputEncoding, SailError, Termination,
+    TraceFormat, starting_vessel,
+};
+
+// Savefile layout: magic, format version, then the sections written by `Machine::save`.
+const SAVE_MAGIC: &[u8; 8] = b"VELOSAVE";
+const SAVE_VERSION: u32 = 1;
+
+/// The result of a single `Machine::step`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum StepOutcome {
+    Running,                 // The cycle completed; the program continues.
+    Output(Vec<u8>),         // The cycle wrote these bytes of program output.
+    Terminated(Termination), // The program has halted.
+    NeedsInput,              // `poll_step` is waiting for input to be fed.
+}
+
+/// A Velo program in execution: the Cosmos, the Vessels sailing it and the runtime state.
+pub struct Machine {
+    cosmos: Cosmos,
+    // The original Vessel first, then those spawned by Spawn runes and still sailing.
+    fleet: Vec<Voyage>,
+    config: Config,
+    context: ExecutionContext,
+    steps: u64,
+    // Set once the program halts; further steps report the same termination.
+    termination: Option<Termination>,
+    // Whether the header of the table trace format has been printed.
+    trace_header_printed: bool,
+    // Cells whose Rune has been impacted, when `Config::with_unused_tracking` is on.
+    impacted: HashSet<(usize, usize)>,
+    // States before the most recent steps, oldest first, for `step_back`.
+    history: VecDeque<Snapshot>,
+    // Whether an Output Cycle rune can print the cycle count, which gliding would change.
+    prints_cycles: bool,
+    // When the first step ran, for `Config::with_timeout`.
+    started: Option<Instant>,
+    // A cycle `poll_step` suspended for input: the fleet index of the waiting
+    // Vessel, and the end of the Vessels that move in that cycle.
+    waiting: Option<(usize, usize)>,
+    // Coordinates recorded by SetLabel runes, for GotoLabel.
+    labels: HashMap<u32, (usize, usize)>,
+}
+
+/// The state `step_back` restores: everything a step can change except I/O.
+struct Snapshot {
+    cosmos: Cosmos,
+    fleet: Vec<Voyage>,
+    steps: u64,
+    rng_state: u64,
+    impacted: HashSet<(usize, usize)>,
+    labels: HashMap<u32, (usize, usize)>,
+}
+
+/// One Vessel of the fleet with its own progress tracking.
+#[derive(Clone)]
+struct Voyage {
+    vessel: Vessel,
+    oscillation: OscillationDetector,
+    // Set once this Vessel halts; the rest of the fleet keeps sailing.
+    termination: Option<Termination>,
+}
+
+impl Voyage {
+    fn new(vessel: Vessel) -> Self {
+        Self {
+            vessel,
+            oscillation: OscillationDetector::default(),
+            termination: None,
+        }
+    }
+}
+
+/// Detects a Vessel bouncing between two cells without making progress.
+#[derive(Debug, Clone, Default)]
+struct OscillationDetector {
+    previous: Option<(usize, usize)>,
+    before_previous: Option<(usize, usize)>,
+    cycles: u64,
+}
+
+impl OscillationDetector {
+    /// Records a cycle ending at `position` and returns how many consecutive
+    /// cycles have alternated between two cells with no state change.
+    fn observe(&mut self, position: (usize, usize), state_changed: bool) -> u64 {
+        let oscillating = !state_changed
+            && self.before_previous == Some(position)
+            && self.previous != Some(position);
+
+        self.cycles = if oscillating { self.cycles + 1 } else { 0 };
+        self.before_previous = self.previous;
+        self.previous = Some(position);
+
+        self.cycles
+    }
+}
+
+impl Machine {
+    /// Prepares a Machine, rejecting programs that cannot run at all.
+    pub fn new(cosmos: Cosmos, mut vessel: Vessel, config: Config) -> Result<Self, SailError> {
+        if cosmos.is_empty() {
+            return Err(SailError::EmptyCosmos);
+        }
+
+        // Check for initial velocity requirement (must start on a Thrust rune)
+        if vessel.velocity() == 0 {
+            // Parking is a valid rune, so it gets its own explanation.
+            if cosmos.get(vessel.x(), vessel.y()) == Rune::Parking {
+                return Err(SailError::ParkingStart);
+            }
+            return Err(SailError::NoInitialVelocityOrDirection);
+        }
+
+        if let Some(size) = config.ring_lattice {
+            vessel.use_ring_lattice(size);
+        }
+        vessel.set_reset_velocity_on_turn(config.reset_velocity_on_turn);
+        vessel.set_zero_based_pointer(config.zero_based_pointer);
+        if let Some(cells) = config.lattice_limit {
+            vessel.set_lattice_limit(cells);
+        }
+
+        let mut context = ExecutionContext::new(config.seed);
+        context.set_output_encoding(config.output_encoding);
+        context.set_flush_policy(config.flush_policy);
+        context.set_output_limit(config.output_limit);
+        let (vessel_x, vessel_y) = (vessel.x(), vessel.y());
+
+        let prints_cycles = cosmos.contains(Rune::OutputCycle);
+
+        Ok(Self {
+            cosmos,
+            fleet: vec![Voyage::new(vessel)],
+            config,
+            context,
+            steps: 0,
+            termination: None,
+            trace_header_printed: false,
+            impacted: HashSet::from([(vessel_x, vessel_y)]),
+            history: VecDeque::new(),
+            prints_cycles,
+            started: None,
+            waiting: None,
+            labels: HashMap::new(),
+        })
+    }
+
+    /// Prepares a Machine for a copy of `cosmos`, with the Vessel at the top left corner.
+    ///
+    /// The original Cosmos is left untouched, so one parse can be run many times.
+    pub fn new_from(cosmos: &Cosmos, config: Config) -> Result<Self, SailError> {
+        let vessel = starting_vessel(cosmos, &config);
+        Self::new(cosmos.clone(), vessel, config)
+    }
+
+    // --- Accessors ---
+
+    pub fn cosmos(&self) -> &Cosmos {
+        &self.cosmos
+    }
+
+    /// The original Vessel, which the completion report describes.
+    pub fn vessel(&self) -> &Vessel {
+        &self.fleet[0].vessel
+    }
+
+    /// The original Vessel followed by the spawned Vessels still sailing.
+    pub fn vessels(&self) -> impl Iterator<Item = &Vessel> {
+        self.fleet.iter().map(|voyage| &voyage.vessel)
+    }
+
+    pub fn steps(&self) -> u64 {
+        self.steps
+    }
+
+    /// Non-Void Runes no Vessel has impacted so far, as `(x, y, rune)` in row order.
+    ///
+    /// Only tracked with `Config::with_unused_tracking`; otherwise empty.
+    pub fn unused_runes(&self) -> Vec<(usize, usize, Rune)> {
+        if !self.config.track_unused {
+            return Vec::new();
+        }
+
+        (0..self.cosmos.height())
+            .flat_map(|y| (0..self.cosmos.width()).map(move |x| (x, y)))
+            .filter(|coordinate| !self.impacted.contains(coordinate))
+            .map(|(x, y)| (x, y, self.cosmos.get(x, y)))
+            .filter(|&(_, _, rune)| rune != Rune::Void)
+            .collect()
+    }
+
+    // --- Execution ---
+
+    /// Replaces the source of program input (stdin by default).
+    pub fn set_input(&mut self, input: impl BufRead + 'static) {
+        self.context.set_input(input);
+    }
+
+    /// Queues bytes of program input for `poll_step`. From the first call on,
+    /// input comes only from this queue.
+    pub fn feed_input(&mut self, bytes: &[u8]) {
+        self.context.input_queue().feed(bytes);
+    }
+
+    /// Marks the end of the fed input, so Runes still reading see EOF.
+    pub fn close_input(&mut self) {
+        self.context.input_queue().close();
+    }
+
+    /// Replaces the destination of program output (stdout by default).
+    ///
+    /// Output is also returned by `step`, so a host consuming it from there
+    /// can pass `io::sink()` here.
+    pub fn set_output(&mut self, output: impl Write + 'static) {
+        self.context.set_output(output);
+    }
+
+    /// Sets the entries the OutputString rune prints (none by default).
+    pub fn set_string_table(&mut self, strings: Vec<String>) {
+        self.context.set_string_table(strings);
+    }
+
+    /// Replaces the destination of trace and Debug rune lines (stdout by
+    /// default), leaving program output where it is. Flushed when the program halts.
+    pub fn set_trace_output(&mut self, trace: impl Write + 'static) {
+        self.context.set_trace_output(trace);
+    }
+
+    /// Replaces the destination of warnings (stderr by default).
+    pub fn set_diagnostic_output(&mut self, diagnostics: impl Write + 'static) {
+        self.context.set_diagnostic_output(diagnostics);
+    }
+
+    /// Executes a single cycle, moving every sailing Vessel once in fleet order.
+    ///
+    /// Returns the bytes written when an output Rune fired, so hosts can drive
+    /// the program like a generator. Once halted, every call reports the
+    /// termination.
+    ///
+    /// The program halts when every Vessel has halted, with the original
+    /// Vessel's termination, or as soon as any Vessel impacts an Exit rune.
+    pub fn step(&mut self) -> StepOutcome {
+        self.collect_cycle(false)
+    }
+
+    /// Executes a cycle like `step`, but never waits for input: reads come from
+    /// the `feed_input` queue, and a Vessel about to read more than has been
+    /// fed stops the cycle with `NeedsInput`.
+    ///
+    /// The next call resumes the cycle at that Vessel, so a fleet reads its
+    /// input in the same order as with blocking input. Runes reading to a
+    /// newline or a number's end wait for all of it, and Slurp Input waits
+    /// for `close_input`.
+    pub fn poll_step(&mut self) -> StepOutcome {
+        self.context.input_queue();
+        match self.collect_cycle(true) {
+            StepOutcome::Running if self.waiting.is_some() => StepOutcome::NeedsInput,
+            outcome => outcome,
+        }
+    }
+
+    fn collect_cycle(&mut self, poll: bool) -> StepOutcome {
+        if let Some(termination) = self.termination {
+            return StepOutcome::Terminated(termination);
+        }
+
+        self.context.collect_step_output();
+        let termination = self.cycle(poll);
+        let output = self.context.take_step_output();
+        if !output.is_empty() {
+            return StepOutcome::Output(output);
+        }
+
+        match termination {
+            Some(termination) => StepOutcome::Terminated(termination),
+            None => StepOutcome::Running,
+        }
+    }
+
+    /// Executes a cycle like `step`, without collecting its output for the
+    /// host. Returns the termination once the program has halted.
+    ///
+    /// With `poll`, stops before a Vessel that would wait for input, leaving
+    /// the rest of the cycle to the next call.
+    fn cycle(&mut self, poll: bool) -> Option<Termination> {
+        if self.termination.is_some() {
+            return self.termination;
+        }
+        self.started.get_or_insert_with(Instant::now);
+
+        // A suspended cycle was recorded in the history when it started.
+        let resumed = self.waiting.take();
+        if resumed.is_none() && self.config.history_depth > 0 {
+            if self.history.len() == self.config.history_depth {
+                self.history.pop_front();
+            }
+            self.history.push_back(Snapshot {
+                cosmos: self.cosmos.clone(),
+                fleet: self.fleet.clone(),
+                steps: self.steps,
+                rng_state: self.context.rng_state(),
+                impacted: self.impacted.clone(),
+                labels: self.labels.clone(),
+            });
+        }
+
+        // Vessels spawned during this cycle first move in the next one.
+        let (start, end) = resumed.unwrap_or((0, self.fleet.len()));
+        for index in start..end {
+            if self.fleet[index].termination.is_some() {
+                continue;
+            }
+            if poll && !self.input_ready(index) {
+                self.waiting = Some((index, end));
+                return None;
+            }
+            let termination = self.advance(index);
+            if let Some(Termination::Exited(_)) = termination {
+                self.termination = termination;
+                break;
+            }
+            self.fleet[index].termination = termination;
+        }
+
+        // Halted spawned Vessels leave the fleet; the original stays for the report.
+        let mut index = 0;
+        self.fleet.retain(|voyage| {
+            index += 1;
+            index == 1 || voyage.termination.is_none()
+        });
+        if self.termination.is_none() && self.fleet.len() == 1 {
+            self.termination = self.fleet[0].termination;
+        }
+        if self.termination.is_none() {
+            self.termination = self.limit_reached();
+        }
+        if self.termination.is_some() {
+            self.context.flush_output();
+            self.context.flush_trace();
+        }
+        self.termination
+    }
+
+    /// Undoes the most recent `step`, returning false once no recorded history is left.
+    ///
+    /// Only the last `Config::with_history` steps are kept. Output already
+    /// written and input already read are not taken back.
+    pub fn step_back(&mut self) -> bool {
+        let Some(snapshot) = self.history.pop_back() else {
+            return false;
+        };
+
+        self.cosmos = snapshot.cosmos;
+        self.fleet = snapshot.fleet;
+        self.steps = snapshot.steps;
+        *self.context.rng() = Rng::from_seed(snapshot.rng_state);
+        self.impacted = snapshot.impacted;
+        self.labels = snapshot.labels;
+        // Steps are only recorded while the program is running.
+        self.termination = None;
+        self.waiting = None;
+
+        true
+    }
+
+    /// Executes up to `steps` cycles, for hosts that run a slice per frame.
+    ///
+    /// Returns `Terminated` if the program halted within the slice, else
+    /// `Output` with everything written during the slice, else `Running`.
+    pub fn run_for(&mut self, steps: u64) -> StepOutcome {
+        let mut output = Vec::new();
+        for _ in 0..steps {
+            match self.step() {
+                StepOutcome::Terminated(termination) => {
+                    self.context.flush_output();
+                    return StepOutcome::Terminated(termination);
+                }
+                StepOutcome::Output(bytes) => output.extend_from_slice(&bytes),
+                // Only `poll_step` waits for input.
+                StepOutcome::Running | StepOutcome::NeedsInput => (),
+            }
+        }
+
+        if output.is_empty() {
+            StepOutcome::Running
+        } else {
+            StepOutcome::Output(output)
+        }
+    }
+
+    /// Runs the program until it halts.
+    pub fn run(&mut self) -> CompletionReport {
+        loop {
+            // Not `step`, so the output of a long run is not collected as well.
+            if let Some(termination) = self.cycle(false) {
+                self.context.flush_output();
+                return self.completion_report(termination);
+            }
+
+            if !self.config.step_delay.is_zero() {
+                // Show this cycle's output before pausing.
+                self.context.flush_output();
+                thread::sleep(self.config.step_delay);
+            }
+        }
+    }
+
+    /// The completion report of a halted program, or `None` while it runs.
+    ///
+    /// Lets hosts driving the program with `step` report on it without running it again.
+    pub fn report(&self) -> Option<CompletionReport> {
+        self.termination
+            .map(|termination| self.completion_report(termination))
+    }
+
+    fn completion_report(&self, termination: Termination) -> CompletionReport {
+        CompletionReport::new(
+            termination,
+            self.steps,
+            self.context.output_bytes(),
+            &self.fleet[0].vessel,
+        )
+    }
+
+    /// Whether the Vessel at `index` can take its step without waiting for
+    /// input that has not been fed. Mirrors how `advance` picks the Rune.
+    fn input_ready(&self, index: usize) -> bool {
+        let vessel = &self.fleet[index].vessel;
+        if vessel.velocity() == 0 {
+            return true;
+        }
+        let (width, height) = (self.cosmos.width(), self.cosmos.height());
+        let distance = if self.config.velocity_is_step {
+            vessel.velocity()
+        } else {
+            1
+        };
+
+        let (x, y) = match vessel.get_coordinate_ahead(distance) {
+            Ok((x, y)) if x < width && y < height => (x, y),
+            _ if self.config.edge_policy == EdgePolicy::Wrap
+                && vessel.direction() != Direction::None =>
+            {
+                wrapped_coordinate(vessel, distance, width, height)
+            }
+            // The Vessel bounces or leaves the Cosmos without impacting a Rune.
+            _ => return true,
+        };
+        self.context.input_ready(self.cosmos.get(x, y))
+    }
+
+    /// Moves the Vessel at `index` in the fleet one cycle and impacts the Rune it lands on.
+    fn advance(&mut self, index: usize) -> Option<Termination> {
+        let width = self.cosmos.width();
+        let height = self.cosmos.height();
+        let config = &self.config;
+        // Gliding saves cycles, which the other Vessels of a fleet, or a `$`
+        // Rune printing the cycle count, would notice.
+        let skip_void = config.skip_void
+            && !config.velocity_is_step
+            && (!config.trace || config.ignore_void)
+            && self.fleet.len() == 1
+            && !self.prints_cycles;
+        let voyage = &mut self.fleet[index];
+        let vessel = &mut voyage.vessel;
+
+        // The Vessel only moves while the Velocity/Pointer is positive.
+        if vessel.velocity() == 0 {
+            return Some(Termination::Stopped);
+        }
+
+        self.steps += 1;
+        self.context.set_step(self.steps);
+
+        let distance = if config.velocity_is_step {
+            vessel.velocity()
+        } else {
+            1
+        };
+        let mut ahead = vessel.get_coordinate_ahead(distance);
+        let in_bounds = matches!(ahead, Ok((x, y)) if x < width && y < height);
+        if !in_bounds && vessel.direction() != Direction::None {
+            match config.edge_policy {
+                EdgePolicy::Terminate => (),
+                EdgePolicy::Wrap => ahead = Ok(wrapped_coordinate(vessel, distance, width, height)),
+                EdgePolicy::Bounce => {
+                    // Turn around on the edge cell; the next cycle moves back inwards.
+                    vessel.rotate_vessel(Rotation::UTurn);
+                    return None;
+                }
+            }
+        }
+
+        let (x, y) = match ahead {
+            Ok(coordinate) => coordinate,
+            Err(_) => {
+                // Fell off the top or left edge; report the edge cell on the way.
+                let (dx, dy) = vessel.direction().delta();
+                let x = if dx < 0 { 0 } else { vessel.x() };
+                let y = if dy < 0 { 0 } else { vessel.y() };
+                return Some(Termination::NoSignal(x, y));
+            }
+        };
+
+        // Check if the next coordinates are within the Cosmos boundaries.
+        if x >= width || y >= height {
+            return Some(Termination::NoSignal(x.min(width - 1), y.min(height - 1)));
+        }
+
+        let rune = self.cosmos.get(x, y);
+        let state_before = (vessel.velocity(), vessel.lattice_changes());
+
+        // Update the vessel's position.
+        vessel.move_to(x, y);
+        vessel.add_distance(distance as u64);
+
+        if skip_void && rune == Rune::Void {
+            // Scan ahead to the last Void rune before the next instruction or the edge.
+            while let Ok((next_x, next_y)) = vessel.get_next_coordinate() {
+                if next_x >= width
+                    || next_y >= height
+                    || self.cosmos.get(next_x, next_y) != Rune::Void
+                {
+                    break;
+                }
+                vessel.move_to(next_x, next_y);
+                vessel.add_distance(1);
+            }
+        }
+
+        if config.track_unused {
+            self.impacted.insert((vessel.x(), vessel.y()));
+        }
+
+        // Impact the Rune and execute the associated instruction/movement.
+        let impact = vessel.impact_rune(rune, &self.cosmos, &mut self.context);
+
+        // Keep program output in order with the diagnostics printed below.
+        if config.debug || config.trace {
+            self.context.flush_output();
+        }
+
+        // Editor-style `line:col` prefix, when enabled.
+        let position = if config.line_col {
+            format!("{}:{} ", vessel.y() + 1, vessel.x() + 1)
+        } else {
+            String::new()
+        };
+
+        let pointer_view = vessel.pointer_view();
+        let shown: &dyn fmt::Debug = if config.show_pointer {
+            &pointer_view
+        } else {
+            &*vessel
+        };
+
+        if rune == Rune::Debug && config.debug {
+            self.context.trace_line(format_args!(
+                "[Debug] {}Vessel: {:?}. Rune: {:?}",
+                position, shown, rune
+            ));
+        }
+
+        if config.trace && !(config.ignore_void && rune == Rune::Void) {
+            match config.trace_format {
+                TraceFormat::Debug => {
+                    self.context.trace_line(format_args!(
+                        "{}Vessel: {:?}. Rune: {:?}",
+                        position, shown, rune
+                    ));
+                }
+                TraceFormat::Table => {
+                    let (first, second) = if config.line_col {
+                        (("line", vessel.y() + 1), ("col", vessel.x() + 1))
+                    } else {
+                        (("x", vessel.x()), ("y", vessel.y()))
+                    };
+                    if !self.trace_header_printed {
+                        self.context.trace_line(format_args!(
+                            "{:>8} {:>5} {:>5} {:>3} {:>8} {:<16} {:>10}",
+                            "step", first.0, second.0, "dir", "vel", "rune", "cell"
+                        ));
+                        self.trace_header_printed = true;
+                    }
+                    let cell = vessel.current_entropy();
+                    self.context.trace_line(format_args!(
+                        "{:>8} {:>5} {:>5} {:>3} {:>8} {:<16} {:>10}",
+                        self.steps,
+                        first.1,
+                        second.1,
+                        vessel.direction().short(),
+                        vessel.velocity(),
+                        rune.name(),
+                        cell
+                    ));
+                }
+            }
+        }
+
+        if let Impact::Exit(code) = impact {
+            return Some(Termination::Exited(code));
+        }
+
+        if impact == Impact::Skip {
+            // Jump over the next Rune. Past the edge, the next cycle reports NoSignal.
+            let distance = if config.velocity_is_step {
+                vessel.velocity()
+            } else {
+                1
+            };
+            match vessel.get_coordinate_ahead(distance) {
+                Ok((next_x, next_y)) if next_x < width && next_y < height => {
+                    vessel.move_to(next_x, next_y);
+                    vessel.add_distance(distance as u64);
+                }
+                _ if config.edge_policy == EdgePolicy::Wrap => {
+                    let (next_x, next_y) = wrapped_coordinate(vessel, distance, width, height);
+                    vessel.move_to(next_x, next_y);
+                    vessel.add_distance(distance as u64);
+                }
+                _ => (),
+            }
+        }
+
+        match impact {
+            Impact::SetLabel(label) => {
+                self.labels.insert(label, (vessel.x(), vessel.y()));
+            }
+            Impact::GotoLabel(label) => {
+                if let Some(&(label_x, label_y)) = self.labels.get(&label) {
+                    vessel.move_to(label_x, label_y);
+                }
+            }
+            _ => (),
+        }
+
+        let stuck = config.oscillation_limit.is_some_and(|limit| {
+            let state_changed = state_before != (vessel.velocity(), vessel.lattice_changes());
+            voyage
+                .oscillation
+                .observe((vessel.x(), vessel.y()), state_changed)
+                >= limit
+        });
+        let stopped = vessel.velocity() == 0;
+        let over_limit = vessel.lattice_overflowed()
+            || config
+                .lattice_limit
+                .is_some_and(|limit| vessel.lattice_len() > limit);
+
+        if impact == Impact::Spawn {
+            let spawned = vessel.spawn();
+            self.fleet.push(Voyage::new(spawned));
+        }
+        if impact == Impact::Rotate {
+            self.rotate_cosmos();
+        }
+
+        if over_limit {
+            Some(Termination::LatticeLimit)
+        } else if stuck {
+            Some(Termination::StuckOscillation)
+        } else if stopped {
+            Some(Termination::Stopped)
+        } else {
+            None
+        }
+    }
+
+    /// The limit from `Config` the run has reached, if any.
+    fn limit_reached(&self) -> Option<Termination> {
+        let config = &self.config;
+        if config.max_steps.is_some_and(|limit| self.steps >= limit) {
+            Some(Termination::StepLimit)
+        } else if self.context.output_limit_reached() {
+            Some(Termination::OutputLimit)
+        } else if config
+            .timeout
+            .zip(self.started)
+            .is_some_and(|(timeout, started)| started.elapsed() >= timeout)
+        {
+            Some(Termination::Timeout)
+        } else {
+            None
+        }
+    }
+
+    /// Turns the Cosmos clockwise, keeping every Vessel on its Rune and heading.
+    fn rotate_cosmos(&mut self) {
+        let height = self.cosmos.height();
+        self.cosmos = self.cosmos.rotate_clockwise();
+        self.impacted = self
+            .impacted
+            .iter()
+            .map(|&(x, y)| (height - 1 - y, x))
+            .collect();
+        for (x, y) in self.labels.values_mut() {
+            (*x, *y) = (height - 1 - *y, *x);
+        }
+
+        for voyage in &mut self.fleet {
+            let (x, y) = (voyage.vessel.x(), voyage.vessel.y());
+            voyage.vessel.move_to(height - 1 - y, x);
+            // Positions seen before the rotation no longer mean the same cells.
+            voyage.oscillation = OscillationDetector::default();
+        }
+    }
+
+    // --- Savefiles ---
+
+    /// Writes the full machine state (Cosmos, Vessel, Config, RNG and string
+    /// table) to a savefile.
+    ///
+    /// A halted machine's termination is not stored: the restored machine
+    /// reaches it again on its next step. A table trace starts over with its header,
+    /// and oscillation detection starts counting again from zero, as does the
+    /// timeout, from the restored machine's first step. The initial
+    /// lattice and capacity settings are not stored; the lattice they seeded is.
+    /// Neither is the step delay, which only paces a live run, nor unused Rune
+    /// tracking or the `step_back` history, which are off in the restored machine.
+    /// Input is never stored, including bytes fed with `feed_input` and not yet read.
+    pub fn save(&self, mut writer: impl Write) -> io::Result<()> {
+        let w = &mut writer;
+
+        w.write_all(SAVE_MAGIC)?;
+        write_u32(w, SAVE_VERSION)?;
+
+        // Config and runtime state.
+        let config = &self.config;
+        let flags = config.debug as u16
+            | (config.trace as u16) << 1
+            | (config.ignore_void as u16) << 2
+            | (config.skip_void as u16) << 3
+            | ((config.trace_format == TraceFormat::Table) as u16) << 4
+            | (config.velocity_is_step as u16) << 5
+            | (config.reset_velocity_on_turn as u16) << 6
+            | (config.line_col as u16) << 7
+            | (config.show_pointer as u16) << 8
+            | ((config.output_encoding == OutputEncoding::Latin1) as u16) << 9
+            | ((config.output_encoding == OutputEncoding::RawLowByte) as u16) << 10
+            | ((config.edge_policy == EdgePolicy::Wrap) as u16) << 11
+            | ((config.edge_policy == EdgePolicy::Bounce) as u16) << 12
+            | ((config.flush_policy == FlushPolicy::EveryByte) as u16) << 13
+            | ((config.flush_policy == FlushPolicy::OnNewline) as u16) << 14
+            | (config.zero_based_pointer as u16) << 15;
+        w.write_all(&flags.to_le_bytes())?;
+        write_option_u64(w, config.seed)?;
+        write_option_u64(w, config.ring_lattice.map(|size| size as u64))?;
+        write_option_u64(w, config.oscillation_limit)?;
+        write_option_u64(w, config.lattice_limit.map(|cells| cells as u64))?;
+        write_option_u64(w, config.max_steps)?;
+        // Longer than u64::MAX nanoseconds (about 584 years) is stored as that.
+        let timeout = config
+            .timeout
+            .map(|timeout| u64::try_from(timeout.as_nanos()).unwrap_or(u64::MAX));
+        write_option_u64(w, timeout)?;
+        write_option_u64(w, config.output_limit)?;
+        write_u64(w, self.context.rng_state())?;
+        write_u64(w, self.steps)?;
+        write_u64(w, self.context.output_bytes())?;
+
+        // Cosmos, row by row. Jagged rows are padded with Void.
+        self.cosmos.write_grid(w)?;
+
+        // The original Vessel, then the spawned ones still sailing.
+        write_vessel(w, &self.fleet[0].vessel)?;
+        let spawned: Vec<&Vessel> = self.fleet[1..]
+            .iter()
+            .filter(|voyage| voyage.termination.is_none())
+            .map(|voyage| &voyage.vessel)
+            .collect();
+        write_u64(w, spawned.len() as u64)?;
+        for vessel in spawned {
+            write_vessel(w, vessel)?;
+        }
+
+        // A cycle suspended by `poll_step`, as positions among the Vessels above.
+        let saved_position = |index: usize| {
+            let spawned = &self.fleet[1..index.max(1)];
+            index.min(1)
+                + spawned
+                    .iter()
+                    .filter(|voyage| voyage.termination.is_none())
+                    .count()
+        };
+        write_option_u64(
+            w,
+            self.waiting.map(|(index, _)| saved_position(index) as u64),
+        )?;
+        if let Some((_, end)) = self.waiting {
+            write_u64(w, saved_position(end) as u64)?;
+        }
+
+        // The string table, each entry as its length and UTF-8 bytes.
+        let strings = self.context.string_table();
+        write_u64(w, strings.len() as u64)?;
+        for entry in strings {
+            write_u64(w, entry.len() as u64)?;
+            w.write_all(entry.as_bytes())?;
+        }
+
+        // Labels, in order.
+        let mut labels: Vec<_> = self.labels.iter().collect();
+        labels.sort();
+        write_u64(w, labels.len() as u64)?;
+        for (&label, &(x, y)) in labels {
+            write_u32(w, label)?;
+            write_u64(w, x as u64)?;
+            write_u64(w, y as u64)?;
+        }
+
+        w.flush()
+    }
+
+    /// Restores a machine written by `Machine::save`.
+    pub fn load(mut reader: impl Read) -> io::Result<Machine> {
+        let r = &mut reader;
+
+        let mut magic = [0; 8];
+        r.read_exact(&mut magic)?;
+        if &magic != SAVE_MAGIC {
+            return Err(invalid_data("not a Velo savefile"));
+        }
+        let version = read_u32(r)?;
+        if version != SAVE_VERSION {
+            return Err(invalid_data(&format!(
+                "unsupported savefile version {}",
+                version
+            )));
+        }
+
+        let flags = read_u16(r)?;
+        let mut config = Config::new(flags & 1 != 0, flags & 2 != 0, flags & 4 != 0)
+            .with_skip_void(flags & 8 != 0)
+            .with_velocity_is_step(flags & 32 != 0)
+            .with_reset_velocity_on_turn(flags & 64 != 0)
+            .with_line_col(flags & 128 != 0)
+            .with_show_pointer(flags & 256 != 0)
+            .with_zero_based_pointer(flags & 32768 != 0);
+        if flags & 16 != 0 {
+            config = config.with_trace_format(TraceFormat::Table);
+        }
+        if flags & 512 != 0 {
+            config = config.with_output_encoding(OutputEncoding::Latin1);
+        } else if flags & 1024 != 0 {
+            config = config.with_output_encoding(OutputEncoding::RawLowByte);
+        }
+        if flags & 2048 != 0 {
+            config = config.with_edge_policy(EdgePolicy::Wrap);
+        } else if flags & 4096 != 0 {
+            config = config.with_edge_policy(EdgePolicy::Bounce);
+        }
+        if flags & 8192 != 0 {
+            config = config.with_flush_policy(FlushPolicy::EveryByte);
+        } else if flags & 16384 != 0 {
+            config = config.with_flush_policy(FlushPolicy::OnNewline);
+        }
+        if let Some(seed) = read_option_u64(r)? {
+            config = config.with_seed(seed);
+        }
+        let ring_lattice = read_option_u64(r)?;
+        if let Some(size) = ring_lattice {
+            config = config.with_ring_lattice(size as usize);
+        }
+        if let Some(cycles) = read_option_u64(r)? {
+            config = config.with_oscillation_detection(cycles);
+        }
+        if let Some(cells) = read_option_u64(r)? {
+            config = config.with_lattice_limit(cells as usize);
+        }
+        if let Some(steps) = read_option_u64(r)? {
+            config = config.with_max_steps(steps);
+        }
+        if let Some(nanos) = read_option_u64(r)? {
+            config = config.with_timeout(Duration::from_nanos(nanos));
+        }
+        if let Some(bytes) = read_option_u64(r)? {
+            config = config.with_output_limit(bytes);
+        }
+        let rng = Rng::from_seed(read_u64(r)?);
+        let steps = read_u64(r)?;
+        let output_bytes = read_u64(r)?;
+
+        let cosmos = Cosmos::read_grid(r)?;
+        if cosmos.is_empty() {
+            return Err(invalid_data(&SailError::EmptyCosmos.to_string()));
+        }
+
+        let mut fleet = vec![Voyage::new(read_vessel(r, &cosmos, &config)?)];
+        for _ in 0..read_u64(r)? {
+            fleet.push(Voyage::new(read_vessel(r, &cosmos, &config)?));
+        }
+
+        let waiting = match read_option_u64(r)? {
+            Some(index) => Some((index as usize, read_u64(r)? as usize)),
+            None => None,
+        };
+        if matches!(waiting, Some((index, end)) if index >= end || end > fleet.len()) {
+            return Err(invalid_data("a suspended cycle names a missing Vessel"));
+        }
+
+        let mut strings = Vec::new();
+        for _ in 0..read_u64(r)? {
+            let len = read_u64(r)?;
+            // Read through `take` so a corrupt length cannot allocate up front.
+            let mut entry = Vec::new();
+            if r.by_ref().take(len).read_to_end(&mut entry)? as u64 != len {
+                return Err(io::ErrorKind::UnexpectedEof.into());
+            }
+            let entry = String::from_utf8(entry)
+                .map_err(|_| invalid_data("a string table entry is not UTF-8"))?;
+            strings.push(entry);
+        }
+
+        let mut context = ExecutionContext::restore(rng, output_bytes);
+        context.set_string_table(strings);
+        context.set_output_encoding(config.output_encoding);
+        context.set_flush_policy(config.flush_policy);
+        context.set_output_limit(config.output_limit);
+
+        let mut labels = HashMap::new();
+        for _ in 0..read_u64(r)? {
+            let label = read_u32(r)?;
+            let (x, y) = (read_usize(r)?, read_usize(r)?);
+            if x >= cosmos.width() || y >= cosmos.height() {
+                return Err(invalid_data("a label is outside the Cosmos"));
+            }
+            labels.insert(label, (x, y));
+        }
+
+        Ok(Machine {
+            prints_cycles: cosmos.contains(Rune::OutputCycle),
+            cosmos,
+            fleet,
+            config,
+            context,
+            steps,
+            termination: None,
+            trace_header_printed: false,
+            impacted: HashSet::new(),
+            history: VecDeque::new(),
+            started: None,
+            waiting,
+            labels,
+        })
+    }
+}
+
+/// The cell `distance` cells ahead of the Vessel, wrapping around the edges of a `width` by `height` Cosmos.
+fn wrapped_coordinate(
+    vessel: &Vessel,
+    distance: usize,
+    width: usize,
+    height: usize,
+) -> (usize, usize) {
+    let wrap = |position: usize, delta: i32, len: usize| match delta {
+        1 => (position + distance % len) % len,
+        -1 => (position + len - distance % len) % len,
+        _ => position,
+    };
+    let (dx, dy) = vessel.direction().delta();
+
+    (wrap(vessel.x(), dx, width), wrap(vessel.y(), dy, height))
+}
+
+fn write_vessel(w: &mut impl Write, vessel: &Vessel) -> io::Result<()> {
+    write_u64(w, vessel.x() as u64)?;
+    write_u64(w, vessel.y() as u64)?;
+    w.write_all(&[vessel.direction().to_i32() as u8])?;
+    write_u64(w, vessel.velocity() as u64)?;
+    write_u64(w, vessel.data_lattice().len() as u64)?;
+    for &cell in vessel.data_lattice() {
+        write_u32(w, cell)?;
+    }
+    write_u64(w, vessel.stack().len() as u64)?;
+    for &value in vessel.stack() {
+        write_u32(w, value)?;
+    }
+    write_u64(w, vessel.lattice_growths())?;
+    // 0 for an empty direction register, else the direction plus one.
+    let saved_direction = vessel
+        .saved_direction()
+        .map_or(0, |d| (d.to_i32() + 1) as u8);
+    w.write_all(&[saved_direction])?;
+    w.write_all(&[vessel.lattice_overflowed() as u8])?;
+    write_option_u64(w, vessel.register().map(u64::from))?;
+    write_u64(w, vessel.distance_traveled())?;
+    Ok(())
+}
+
+fn read_vessel(r: &mut impl Read, cosmos: &Cosmos, config: &Config) -> io::Result<Vessel> {
+    let x = read_usize(r)?;
+    let y = read_usize(r)?;
+    let direction = Direction::from_i32(read_u8(r)? as i8 as i32);
+    let velocity = read_usize(r)?;
+    let lattice_len = read_u64(r)?;
+    let mut data_lattice = Vec::new();
+    for _ in 0..lattice_len {
+        data_lattice.push(read_u32(r)?);
+    }
+    if x >= cosmos.width() || y >= cosmos.height() {
+        return Err(invalid_data("the vessel is outside the cosmos"));
+    }
+    let mut vessel = Vessel::restore(x, y, direction, velocity, data_lattice);
+    let stack_len = read_u64(r)?;
+    let mut stack = Vec::new();
+    for _ in 0..stack_len {
+        stack.push(read_u32(r)?);
+    }
+    vessel.restore_stack(stack);
+    vessel.restore_lattice_growths(read_u64(r)?);
+    let saved_direction = match read_u8(r)? {
+        0 => None,
+        d => Some(Direction::from_i32(d as i32 - 1)),
+    };
+    vessel.restore_saved_direction(saved_direction);
+    vessel.restore_lattice_overflowed(read_u8(r)? != 0);
+    let register = read_option_u64(r)?.map(|value| value as u32);
+    vessel.restore_register(register);
+    vessel.add_distance(read_u64(r)?);
+    if let Some(size) = config.ring_lattice {
+        vessel.use_ring_lattice(size);
+    }
+    if let Some(cells) = config.lattice_limit {
+        vessel.set_lattice_limit(cells);
+    }
+    vessel.set_reset_velocity_on_turn(config.reset_velocity_on_turn);
+    vessel.set_zero_based_pointer(config.zero_based_pointer);
+    Ok(vessel)
+}
+
+fn invalid_data(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+fn write_u32(w: &mut impl Write, value: u32) -> io::Result<()> {
+    w.write_all(&value.to_le_bytes())
+}
+
+fn write_u64(w: &mut impl Write, value: u64) -> io::Result<()> {
+    w.write_all(&value.to_le_bytes())
+}
+
+fn write_option_u64(w: &mut impl Write, value: Option<u64>) -> io::Result<()> {
+    match value {
+        Some(value) => {
+            w.write_all(&[1])?;
+            write_u64(w, value)
+        }
+        None => w.write_all(&[0]),
+    }
+}
+
+fn read_u8(r: &mut impl Read) -> io::Result<u8> {
+    let mut buffer = [0; 1];
+    r.read_exact(&mut buffer)?;
+    Ok(buffer[0])
+}
+
+fn read_u16(r: &mut impl Read) -> io::Result<u16> {
+    let mut buffer = [0; 2];
+    r.read_exact(&mut buffer)?;
+    Ok(u16::from_le_bytes(buffer))
+}
+
+fn read_u32(r: &mut impl Read) -> io::Result<u32> {
+    let mut buffer = [0; 4];
+    r.read_exact(&mut buffer)?;
+    Ok(u32::from_le_bytes(buffer))
+}
+
+fn read_u64(r: &mut impl Read) -> io::Result<u64> {
+    let mut buffer = [0; 8];
+    r.read_exact(&mut buffer)?;
+    Ok(u64::from_le_bytes(buffer))
+}
+
+fn read_usize(r: &mut impl Read) -> io::Result<usize> {
+    usize::try_from(read_u64(r)?).map_err(|_| invalid_data("value does not fit in usize"))
+}
+
+fn read_option_u64(r: &mut impl Read) -> io::Result<Option<u64>> {
+    match read_u8(r)? {
+        0 => Ok(None),
+        1 => Ok(Some(read_u64(r)?)),
+        _ => Err(invalid_data("malformed optional value")),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::{MAX_OUTPUT_REPEAT, MAX_VELOCITY, VesselState};
+    use crate::testing::{FlushLog, SharedBuffer, machine, run};
+    use std::time::Instant;
+
+    fn save(machine: &Machine) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        machine.save(&mut bytes).expect("saving to memory succeeds");
+        bytes
+    }
+
+    #[test]
+    fn a_loaded_savefile_resumes_where_the_run_was_saved() {
+        let source = ">++++++?+++   ";
+        let config = || Config::new(false, false, false).with_seed(7);
+
+        let (mut uninterrupted, _) = machine(source, "", config());
+        let expected = uninterrupted.run();
+
+        let (mut saved, _) = machine(source, "", config());
+        for _ in 0..5 {
+            saved.step();
+        }
+        let mut loaded = Machine::load(save(&saved).as_slice()).expect("the savefile loads");
+        assert_eq!(loaded.steps(), 5);
+
+        let report = loaded.run();
+        assert_eq!(report.termination(), expected.termination());
+        assert_eq!(report.steps(), expected.steps());
+        let (resumed, original) = (loaded.vessel(), uninterrupted.vessel());
+        assert_eq!((resumed.x(), resumed.y()), (original.x(), original.y()));
+        assert_eq!(resumed.velocity(), original.velocity());
+        assert_eq!(resumed.data_lattice(), original.data_lattice());
+    }
+
+    #[test]
+    fn load_rejects_savefiles_no_machine_could_run() {
+        let bytes = save(&machine(">+ ", "", Config::new(false, false, false)).0);
+        let patched = |offset: usize, value: u64| {
+            let mut bytes = bytes.clone();
+            bytes[offset..offset + 8].copy_from_slice(&value.to_le_bytes());
+            Machine::load(bytes.as_slice())
+                .err()
+                .map(|error| error.kind())
+        };
+
+        // Without a seed or any limit the Cosmos width starts at byte 45 and
+        // the Vessel x follows the height and the 3 Runes.
+        assert_eq!(patched(45, 0), Some(io::ErrorKind::InvalidData));
+        assert_eq!(patched(45 + 16 + 3, 3), Some(io::ErrorKind::InvalidData));
+        assert_eq!(patched(45 + 16 + 3, 2), None);
+    }
+
+    #[test]
+    fn a_savefile_keeps_the_trace_format() {
+        let config = Config::new(false, false, false).with_trace_format(TraceFormat::Table);
+        let loaded = Machine::load(save(&machine(">+", "", config).0).as_slice());
+        let format = loaded.map(|machine| machine.config.trace_format).ok();
+        assert_eq!(format, Some(TraceFormat::Table));
+    }
+
+    #[test]
+    fn thrust_if_nonzero_only_speeds_up_on_a_nonzero_cell() {
+        let velocity_after = |source| {
+            let (mut machine, _) = machine(source, "", Config::new(false, false, false));
+            machine.run();
+            machine.vessel().velocity()
+        };
+        assert_eq!(velocity_after(">T  "), 1);
+        assert_eq!(velocity_after(">+T  "), 2);
+    }
+
+    #[test]
+    fn ring_lattice_config_wraps_the_pointer() {
+        let source = ">+>+  ";
+        let (mut unbounded, _) = machine(source, "", Config::new(false, false, false));
+        unbounded.run();
+        assert_eq!(unbounded.vessel().data_lattice()[..3], [0, 1, 1]);
+
+        let ring = Config::new(false, false, false).with_ring_lattice(1);
+        let (mut wrapped, _) = machine(source, "", ring);
+        wrapped.run();
+        assert_eq!(wrapped.vessel().data_lattice(), [2]);
+
+        let loaded = Machine::load(save(&wrapped).as_slice()).expect("the savefile loads");
+        assert_eq!(loaded.config.ring_lattice, Some(1));
+        assert_eq!(loaded.vessel().data_lattice(), [2]);
+    }
+
+    #[test]
+    fn skip_if_less_jumps_over_the_next_rune_only_below_the_next_cell() {
+        let second_cell_after = |source| {
+            let (mut machine, _) = machine(source, "", Config::new(false, false, false));
+            machine.run();
+            machine.vessel().data_lattice()[1]
+        };
+        // Cell 1 equals cell 2, so `+` runs.
+        assert_eq!(second_cell_after(">L+  "), 1);
+        // `>+<` sets cell 2 to 1 first, so cell 1 is less and `+` is skipped.
+        assert_eq!(second_cell_after(">>+<L+  "), 0);
+    }
+
+    #[test]
+    fn a_vessel_bouncing_between_two_cells_is_stuck() {
+        // `>+<` sets cell 2, so `L` skips the first `U`, and the Vessel then
+        // bounces between the two `U` Runes forever.
+        let config = Config::new(false, false, false).with_oscillation_detection(10);
+        let (mut stuck, _) = machine(">>+<LUU", "", config);
+        let report = stuck.run();
+        assert_eq!(report.termination(), Termination::StuckOscillation);
+        assert!(report.steps() < 30);
+
+        let loaded = Machine::load(save(&stuck).as_slice()).expect("the savefile loads");
+        assert_eq!(loaded.config.oscillation_limit, Some(10));
+    }
+
+    #[test]
+    fn step_reports_the_output_of_each_cycle() {
+        let (mut machine, output) = machine(">+.+.", "", Config::new(false, false, false));
+        assert_eq!(machine.step(), StepOutcome::Running);
+        assert_eq!(machine.step(), StepOutcome::Output(vec![1]));
+        assert_eq!(machine.step(), StepOutcome::Running);
+        assert_eq!(machine.step(), StepOutcome::Output(vec![2]));
+        let ended = StepOutcome::Terminated(Termination::NoSignal(4, 0));
+        assert_eq!(machine.step(), ended);
+        assert_eq!(machine.step(), ended);
+        assert_eq!(output.contents(), [1, 2]);
+    }
+
+    #[test]
+    fn run_does_not_collect_step_output() {
+        let (mut machine, output) = machine(">+.+.", "", Config::new(false, false, false));
+        machine.run();
+        assert_eq!(output.contents(), [1, 2]);
+        assert!(machine.context.take_step_output().is_empty());
+    }
+
+    #[test]
+    fn output_velocity_prints_the_pointer_in_decimal() {
+        let config = Config::new(false, false, false);
+        assert_eq!(run(">>>>>>>>>>>>!", "", config).1, b"12");
+    }
+
+    #[test]
+    fn input_runes_read_from_the_machine_input() {
+        let config = || Config::new(false, false, false);
+        assert_eq!(run(">,.,.,.", "Hi", config()).1, b"Hi\0");
+        assert_eq!(run(">&.&.", "0x48 0b1101001", config()).1, b"Hi");
+    }
+
+    #[test]
+    fn input_available_peeks_without_consuming() {
+        let config = || Config::new(false, false, false);
+        assert_eq!(run(">E.,.E.", "a", config()).1, [1, b'a', 0]);
+        assert_eq!(run(">E.", "", config()).1, [0]);
+    }
+
+    #[test]
+    fn velocity_is_step_moves_velocity_cells_per_cycle() {
+        let source = ">>+ +.";
+        let config = || Config::new(false, false, false);
+        assert_eq!(run(source, "", config()).1, [2]);
+        let (termination, output) = run(source, "", config().with_velocity_is_step(true));
+        assert_eq!(output, [0]);
+        assert_eq!(termination, Termination::NoSignal(5, 0));
+
+        let saved = machine(source, "", config().with_velocity_is_step(true)).0;
+        let loaded = Machine::load(save(&saved).as_slice()).map(|m| m.config.velocity_is_step);
+        assert!(loaded.expect("the savefile loads"));
+    }
+
+    #[test]
+    fn input_or_skip_jumps_over_the_next_rune_at_the_end_of_input() {
+        let config = || Config::new(false, false, false);
+        assert_eq!(run(">~.~.+.", "a", config()).1, b"a\x01");
+    }
+
+    #[test]
+    fn step_delay_sleeps_after_every_cycle() {
+        let delay = Duration::from_millis(10);
+        let config = Config::new(false, false, false).with_step_delay(delay);
+        let (mut machine, _) = machine(">+++", "", config);
+        let started = Instant::now();
+        assert_eq!(machine.run().steps(), 4);
+        assert!(started.elapsed() >= delay * 3);
+    }
+
+    #[test]
+    fn input_length_consumes_a_line_per_rune() {
+        let config = Config::new(false, false, false);
+        assert_eq!(run(">C.C.C.C.", "hello\n\nab", config).1, [5, 0, 2, 0]);
+    }
+
+    #[test]
+    fn advance_and_retreat_by_cell_shift_the_pointer() {
+        // Cell 1 holds 3, so `}` moves the pointer to cell 4; `{` there (2) moves it to 2.
+        let source = ">+++}++{V.";
+        assert_eq!(run(source, "", Config::default()).1, [2]);
+    }
+
+    #[test]
+    fn advancing_by_a_full_cell_allocates_a_bounded_lattice() {
+        let (mut machine, _) = machine(">&}+", "4294967295", Config::default());
+        assert_eq!(machine.run().termination(), Termination::NoSignal(3, 0));
+        assert_eq!(machine.vessel().velocity(), MAX_VELOCITY);
+        assert!(machine.vessel().data_lattice().len() <= MAX_VELOCITY + 16);
+    }
+
+    #[test]
+    fn new_from_runs_one_parsed_cosmos_many_times() {
+        let cosmos = Cosmos::parse(">+.+.").unwrap();
+        for _ in 0..2 {
+            let mut machine = Machine::new_from(&cosmos, Config::default()).unwrap();
+            let output = SharedBuffer::default();
+            machine.set_output(output.clone());
+            assert_eq!(machine.run().termination(), Termination::NoSignal(4, 0));
+            assert_eq!(output.contents(), [1, 2]);
+        }
+        assert_eq!(cosmos.to_string(), ">+.+.\n");
+    }
+
+    #[test]
+    fn the_stack_pushes_adds_and_pops_cells() {
+        // Pushes 5 and u32::MAX, adds them (wrapping to 4), then pops 4 and the empty 0.
+        let source = ">&(&(A).).";
+        let (mut machine, output) = machine(source, "5 4294967295", Config::default());
+        let mut saved = None;
+        while !matches!(machine.step(), StepOutcome::Terminated(_)) {
+            if machine.steps() == 4 {
+                saved = Some(save(&machine));
+            }
+        }
+        assert_eq!(output.contents(), [4, 0]);
+
+        let mut loaded = Machine::load(saved.expect("saved mid-run").as_slice()).unwrap();
+        assert_eq!(loaded.vessel().stack(), [5, u32::MAX]);
+        let resumed = SharedBuffer::default();
+        loaded.set_output(resumed.clone());
+        loaded.run();
+        assert_eq!(resumed.contents(), [4, 0]);
+    }
+
+    #[test]
+    fn report_is_available_once_stepping_halts() {
+        let (mut machine, _) = machine(">+.", "", Config::default());
+        assert!(machine.report().is_none());
+        while !matches!(machine.step(), StepOutcome::Terminated(_)) {}
+        let report = machine.report().expect("the program halted");
+        assert_eq!(report.termination(), Termination::NoSignal(2, 0));
+        assert_eq!((report.steps(), report.output_bytes()), (3, 1));
+    }
+
+    #[test]
+    fn exit_takes_its_code_from_the_cell_and_saturates() {
+        let config = || Config::default();
+        assert_eq!(
+            run(">+++Q.", "", config()),
+            (Termination::Exited(3), vec![])
+        );
+        assert_eq!(run(">&Q", "300", config()).0, Termination::Exited(63));
+    }
+
+    #[test]
+    fn perpendicular_thrust_can_reset_the_velocity() {
+        let source = ">>v\n  !";
+        assert_eq!(run(source, "", Config::default()).1, b"2");
+        let config = Config::default().with_reset_velocity_on_turn(true);
+        assert_eq!(run(source, "", config.clone()).1, b"1");
+
+        let loaded = Machine::load(save(&machine(source, "", config).0).as_slice());
+        assert!(loaded.unwrap().config.reset_velocity_on_turn);
+    }
+
+    #[test]
+    fn mod_divides_by_the_next_cell_and_ignores_zero() {
+        let remainder = |cells: Vec<u32>| {
+            let config = Config::default().with_initial_lattice(cells);
+            run(">%.", "", config).1
+        };
+        assert_eq!(remainder(vec![0, 17, 5]), [2]);
+        assert_eq!(remainder(vec![0, 17, 0]), [17]);
+    }
+
+    #[test]
+    fn steer_by_ahead_turns_right_before_empty_cells() {
+        // Turned right (down), the Vessel leaves the one-row grid at the `W`.
+        let config = || Config::default();
+        assert_eq!(run(">W+", "", config()).0, Termination::NoSignal(2, 0));
+        assert_eq!(run(">W ", "", config()).0, Termination::NoSignal(1, 0));
+        assert_eq!(run(">W", "", config()).0, Termination::NoSignal(1, 0));
+        assert_eq!(run(">W \n .", "", config()).0, Termination::NoSignal(1, 1));
+    }
+
+    #[test]
+    fn spawned_vessels_sail_alongside_the_original() {
+        let (mut machine, output) = machine(">+*.\n  -\n  .", "", Config::default());
+        for _ in 0..3 {
+            machine.step();
+        }
+        let headings: Vec<Direction> = machine.vessels().map(Vessel::direction).collect();
+        assert_eq!(headings, [Direction::Right, Direction::Down]);
+
+        let printed = output.contents().len();
+        let mut loaded = Machine::load(save(&machine).as_slice()).unwrap();
+        assert_eq!(loaded.vessels().count(), 2);
+        let resumed = SharedBuffer::default();
+        loaded.set_output(resumed.clone());
+
+        let report = machine.run();
+        assert_eq!(output.contents(), [1, 0]);
+        assert_eq!(report.termination(), Termination::NoSignal(3, 0));
+        assert_eq!(loaded.run().termination(), report.termination());
+        assert_eq!(resumed.contents(), output.contents()[printed..]);
+    }
+
+    #[test]
+    fn skip_void_keeps_spawned_vessels_in_step() {
+        // The spawned Vessel crosses the Void column while the original charges its cell.
+        let source = ">*+++.\n\n\n\n +\n .";
+        for skip_void in [false, true] {
+            let config = Config::default().with_skip_void(skip_void);
+            assert_eq!(run(source, "", config).1, [3, 1]);
+        }
+    }
+
+    #[test]
+    fn rotate_carries_the_vessel_with_the_grid() {
+        let (mut machine, _) = machine(">R\n+.", "", Config::default());
+        let snapshot = machine.cosmos().clone();
+        assert_eq!(machine.step(), StepOutcome::Running);
+        assert_eq!((machine.vessel().x(), machine.vessel().y()), (1, 1));
+        assert_eq!(machine.cosmos().get(1, 1), Rune::Rotate);
+        assert_eq!(
+            machine.step(),
+            StepOutcome::Terminated(Termination::NoSignal(1, 1))
+        );
+        assert_eq!(snapshot.to_string(), ">R\n+.\n");
+    }
+
+    #[test]
+    fn reports_count_the_lattice_growths() {
+        // Cell 101 is past the initial lattice, and cell 301 past the grown one.
+        let (mut running, _) = machine(">&}+&}+", "100 200", Config::default());
+        while running.vessel().lattice_growths() == 0 {
+            running.step();
+        }
+        let mut loaded = Machine::load(save(&running).as_slice()).unwrap();
+        loaded.set_input(io::Cursor::new("200"));
+        assert_eq!(running.run().lattice_growths(), 2);
+        assert_eq!(loaded.run().lattice_growths(), 2);
+    }
+
+    #[test]
+    fn newline_prints_a_line_feed_whatever_the_cell() {
+        assert_eq!(run(">N+N", "", Config::default()).1, b"\n\n");
+    }
+
+    #[test]
+    fn steer_by_value_branches_on_the_cell_modulo_four() {
+        // Heading down at `J`, the Vessel goes on, turns right or left and leaves the
+        // grid, or turns back into the opposing `v` and stops.
+        let exit_for = |cell: u32| {
+            let config = Config::default().with_initial_lattice(vec![0, cell]);
+            run(">v \n J \n   ", "", config).0
+        };
+        let exits: Vec<Termination> = [0, 1, 2, 3, 5].into_iter().map(exit_for).collect();
+        assert_eq!(
+            exits,
+            [
+                Termination::NoSignal(1, 2),
+                Termination::NoSignal(0, 1),
+                Termination::Stopped,
+                Termination::NoSignal(2, 1),
+                Termination::NoSignal(0, 1),
+            ]
+        );
+    }
+
+    #[test]
+    fn a_savefile_keeps_the_line_col_setting() {
+        let config = Config::new(false, true, false).with_line_col(true);
+        let loaded = Machine::load(save(&machine(">+", "", config).0).as_slice());
+        assert!(loaded.unwrap().config.line_col);
+    }
+
+    #[test]
+    fn run_for_stops_after_the_given_steps() {
+        let (mut machine, _) = machine(">+++.+.", "", Config::default());
+        assert_eq!(machine.run_for(3), StepOutcome::Running);
+        assert_eq!(machine.steps(), 3);
+        assert_eq!(machine.run_for(0), StepOutcome::Running);
+        assert_eq!(machine.run_for(2), StepOutcome::Output(vec![3]));
+        let ended = StepOutcome::Terminated(Termination::NoSignal(6, 0));
+        assert_eq!(machine.run_for(10), ended);
+        assert_eq!(machine.steps(), 7);
+    }
+
+    #[test]
+    fn a_savefile_keeps_the_show_pointer_setting() {
+        let config = Config::new(false, true, false).with_show_pointer(true);
+        let loaded = Machine::load(save(&machine(">+", "", config).0).as_slice());
+        assert!(loaded.unwrap().config.show_pointer);
+    }
+
+    #[test]
+    fn add_next_adds_the_next_cell_and_wraps() {
+        let sum = |cells: Vec<u32>| {
+            let config = Config::default().with_initial_lattice(cells);
+            run(">S.", "", config).1
+        };
+        assert_eq!(sum(vec![0, 4, 3]), [7]);
+        assert_eq!(sum(vec![0, u32::MAX, 2]), [1]);
+    }
+
+    #[test]
+    fn restore_dir_returns_to_the_saved_heading() {
+        let config = || Config::default();
+        // Nothing saved yet: the heading is kept.
+        assert_eq!(run(">k  ", "", config()).0, Termination::NoSignal(3, 0));
+        // `k` turns the Vessel heading down from `v` back to the right.
+        let source = ">K+v \n   k ";
+        assert_eq!(run(source, "", config()).0, Termination::NoSignal(4, 1));
+        assert_eq!(
+            run(">K+v \n     ", "", config()).0,
+            Termination::NoSignal(3, 1)
+        );
+
+        let (mut machine, _) = machine(source, "", config());
+        machine.run_for(2);
+        let mut loaded = Machine::load(save(&machine).as_slice()).unwrap();
+        assert_eq!(loaded.vessel().saved_direction(), Some(Direction::Right));
+        assert_eq!(loaded.run().termination(), Termination::NoSignal(4, 1));
+    }
+
+    #[test]
+    fn buffered_output_is_flushed_before_input_and_when_the_program_halts() {
+        let (mut machine, _) = machine(">+..~..", "", Config::default());
+        let output = FlushLog::default();
+        machine.set_output(output.clone());
+        machine.run_for(3);
+        assert!(output.flushes().is_empty());
+        machine.run_for(1);
+        assert_eq!(output.flushes(), vec![2]);
+        machine.run();
+        assert_eq!(output.flushes().last(), Some(&3));
+    }
+
+    #[test]
+    fn sub_next_subtracts_the_next_cell_and_stops_at_zero() {
+        let difference = |cells: Vec<u32>| {
+            let config = Config::default().with_initial_lattice(cells);
+            run(">M.", "", config).1
+        };
+        assert_eq!(difference(vec![0, 7, 3]), [4]);
+        assert_eq!(difference(vec![0, 2, 3]), [0]);
+    }
+
+    #[test]
+    fn unused_runes_are_those_never_impacted() {
+        let config = Config::default().with_unused_tracking(true);
+        let (mut tracked, _) = machine(">+ +\n v<", "", config);
+        tracked.run();
+        assert_eq!(
+            tracked.unused_runes(),
+            [(1, 1, Rune::ThrustDown), (2, 1, Rune::ThrustLeft)]
+        );
+
+        let (mut untracked, _) = machine(">+ +\n v<", "", Config::default());
+        untracked.run();
+        assert!(untracked.unused_runes().is_empty());
+    }
+
+    #[test]
+    fn peek_at_reads_an_absolute_cell_without_growing_the_lattice() {
+        let config = Config::default().with_initial_lattice(vec![9, 3, 0, 7]);
+        assert_eq!(run(">@.", "", config).1, [7]);
+
+        let config = Config::default().with_initial_lattice(vec![9, u32::MAX]);
+        let (mut machine, output) = machine(">@.", "", config);
+        machine.run();
+        assert_eq!(output.contents(), [0]);
+        assert_eq!(machine.vessel().data_lattice(), [9, 0]);
+        assert_eq!(machine.vessel().lattice_growths(), 0);
+    }
+
+    #[test]
+    fn trace_lines_go_to_the_trace_output_only() {
+        let traced = |config: Config| {
+            let (mut machine, output) = machine(">+.", "", config);
+            let trace = SharedBuffer::default();
+            machine.set_trace_output(trace.clone());
+            machine.run();
+            assert_eq!(output.contents(), [1]);
+            String::from_utf8(trace.contents()).unwrap()
+        };
+
+        let trace = traced(Config::new(false, true, false));
+        assert_eq!(trace.lines().count(), 2);
+        assert!(trace.lines().all(|line| line.starts_with("Vessel: ")));
+
+        let trace = traced(Config::new(false, true, false).with_line_col(true));
+        assert!(trace.lines().all(|line| line.starts_with("1:")));
+
+        let config = Config::new(false, true, false)
+            .with_trace_format(TraceFormat::Table)
+            .with_line_col(true);
+        let header = traced(config).lines().next().unwrap().to_string();
+        assert!(header.contains(" line ") && header.contains(" col "));
+    }
+
+    #[test]
+    fn output_if_nonzero_skips_zero_cells() {
+        assert_eq!(run(">:+:", "", Config::default()).1, [1]);
+        assert_eq!(run(">.+.", "", Config::default()).1, [0, 1]);
+    }
+
+    #[test]
+    fn the_lattice_limit_stops_deep_lattice_use() {
+        let config = Config::default().with_lattice_limit(64);
+        let (mut machine, _) = machine(">&}+", "100", config);
+        let report = machine.run();
+        assert_eq!(report.termination(), Termination::LatticeLimit);
+        // The lattice is refused the cell instead of growing to it.
+        assert!(report.peak_lattice_cells() <= 64);
+
+        let loaded = Machine::load(save(&machine).as_slice()).expect("the savefile loads");
+        assert_eq!(loaded.config.lattice_limit, Some(64));
+
+        let config = Config::default().with_lattice_limit(64);
+        assert_eq!(run(">+}+", "", config).0, Termination::NoSignal(3, 0));
+    }
+
+    #[test]
+    fn step_back_undoes_the_recorded_steps() {
+        let (mut machine, _) = machine(">+++", "", Config::default().with_history(2));
+        machine.run_for(3);
+        assert_eq!(machine.vessel().data_lattice()[1], 3);
+
+        assert!(machine.step_back());
+        assert_eq!(machine.steps(), 2);
+        assert_eq!(machine.vessel().data_lattice()[1], 2);
+        assert_eq!(machine.vessel().x(), 2);
+        assert!(machine.step_back());
+        assert!(!machine.step_back());
+        assert_eq!(machine.steps(), 1);
+
+        // Stepping again replays the same program.
+        machine.run_for(2);
+        assert_eq!(machine.vessel().data_lattice()[1], 3);
+    }
+
+    #[test]
+    fn step_back_can_undo_the_end_of_the_program() {
+        let (mut machine, _) = machine(">+", "", Config::default().with_history(1));
+        machine.run_for(2);
+        assert!(matches!(machine.step(), StepOutcome::Terminated(_)));
+        assert!(machine.step_back());
+        assert_eq!(
+            machine.step(),
+            StepOutcome::Terminated(Termination::NoSignal(1, 0))
+        );
+    }
+
+    #[test]
+    fn clear_all_zeroes_every_cell_and_keeps_the_allocation() {
+        let config = Config::default().with_initial_lattice(vec![4, 3, 0, 9]);
+        let (mut machine, output) = machine(">(X.", "", config);
+        machine.run();
+        assert_eq!(output.contents(), [0]);
+        assert_eq!(machine.vessel().data_lattice(), [0, 0, 0, 0]);
+        assert_eq!(machine.vessel().stack(), [3]);
+        assert_eq!(machine.vessel().velocity(), 1);
+    }
+
+    #[test]
+    fn output_encodings_write_cells_as_configured() {
+        let output = |cell: u32, encoding: OutputEncoding| {
+            let config = Config::default()
+                .with_initial_lattice(vec![0, cell])
+                .with_output_encoding(encoding);
+            run(">.", "", config).1
+        };
+        assert_eq!(output(0xE9, OutputEncoding::Utf8), [0xC3, 0xA9]);
+        assert_eq!(output(0xE9, OutputEncoding::Latin1), [0xE9]);
+        assert_eq!(output(0x1E9, OutputEncoding::Latin1), []);
+        assert_eq!(output(0x1E9, OutputEncoding::RawLowByte), [0xE9]);
+        assert_eq!(output(0xD800, OutputEncoding::Utf8), []);
+
+        for encoding in [OutputEncoding::Latin1, OutputEncoding::RawLowByte] {
+            let config = Config::default().with_output_encoding(encoding);
+            let (machine, _) = machine(">.", "", config);
+            let loaded = Machine::load(save(&machine).as_slice()).expect("the savefile loads");
+            assert_eq!(loaded.context.output_encoding(), encoding);
+        }
+    }
+
+    /// Where the Vessel of `source` is, and where it heads, after `steps` cycles
+    /// under `policy`.
+    fn position_after(source: &str, policy: EdgePolicy, steps: u64) -> (usize, usize, Direction) {
+        let (mut machine, _) = machine(source, "", Config::default().with_edge_policy(policy));
+        assert_eq!(machine.run_for(steps), StepOutcome::Running);
+        let vessel = machine.vessel();
+        (vessel.x(), vessel.y(), vessel.direction())
+    }
+
+    #[test]
+    fn wrapping_reenters_from_the_opposite_edge() {
+        let wrap = EdgePolicy::Wrap;
+        assert_eq!(position_after("<  ", wrap, 1), (2, 0, Direction::Left));
+        assert_eq!(position_after(">  ", wrap, 3), (0, 0, Direction::Right));
+        assert_eq!(position_after("^\n \n ", wrap, 1), (0, 2, Direction::Up));
+        assert_eq!(position_after("v\n \n ", wrap, 3), (0, 0, Direction::Down));
+    }
+
+    #[test]
+    fn bouncing_turns_around_on_the_edge_cell() {
+        let bounce = EdgePolicy::Bounce;
+        assert_eq!(position_after("<  ", bounce, 1), (0, 0, Direction::Right));
+        assert_eq!(position_after(">  ", bounce, 3), (2, 0, Direction::Left));
+        assert_eq!(
+            position_after("^\n \n ", bounce, 1),
+            (0, 0, Direction::Down)
+        );
+        assert_eq!(position_after("v\n \n ", bounce, 3), (0, 2, Direction::Up));
+    }
+
+    #[test]
+    fn terminating_reports_the_edge_cell() {
+        let ends =
+            ["<  ", ">  ", "^\n \n ", "v\n \n "].map(|source| run(source, "", Config::default()).0);
+        assert_eq!(
+            ends,
+            [
+                Termination::NoSignal(0, 0),
+                Termination::NoSignal(2, 0),
+                Termination::NoSignal(0, 0),
+                Termination::NoSignal(0, 2),
+            ]
+        );
+    }
+
+    #[test]
+    fn a_savefile_keeps_the_edge_policy() {
+        for policy in [EdgePolicy::Wrap, EdgePolicy::Bounce] {
+            let config = Config::default().with_edge_policy(policy);
+            let (machine, _) = machine(">  ", "", config);
+            let loaded = Machine::load(save(&machine).as_slice()).expect("the savefile loads");
+            assert_eq!(loaded.config.edge_policy, policy);
+        }
+    }
+
+    #[test]
+    fn output_cycle_prints_the_cycle_number() {
+        assert_eq!(run(">+$N$", "", Config::default()).1, b"2\n4");
+    }
+
+    #[test]
+    fn skip_void_keeps_the_cycles_printed_by_output_cycle() {
+        let printed =
+            |skip_void: bool| run(">    $", "", Config::default().with_skip_void(skip_void));
+        assert_eq!(printed(false).1, b"5");
+        assert_eq!(printed(true), printed(false));
+    }
+
+    #[test]
+    fn halt_if_fast_stops_above_the_cell_value() {
+        let config = Config::default().with_initial_lattice(vec![0, 1]);
+        assert_eq!(
+            run(">F.", "", config),
+            (Termination::NoSignal(2, 0), vec![1])
+        );
+        assert_eq!(
+            run(">F.", "", Config::default()),
+            (Termination::Stopped, vec![])
+        );
+    }
+
+    #[test]
+    fn load_width_and_height_read_the_cosmos_dimensions() {
+        assert_eq!(run(">w.h.\n\n", "", Config::default()).1, [5, 2]);
+
+        // Transposing swaps the two Runes along with the dimensions.
+        let cosmos = Cosmos::parse(">w.h.\n\n").unwrap().transpose();
+        let mut machine = Machine::new_from(&cosmos, Config::default()).unwrap();
+        let output = SharedBuffer::default();
+        machine.set_output(output.clone());
+        machine.run();
+        assert_eq!(output.contents(), [5, 2]);
+    }
+
+    #[test]
+    fn flush_policies_choose_when_output_is_flushed() {
+        let flushes_with = |flush_policy| {
+            let config = Config::default().with_flush_policy(flush_policy);
+            let (mut machine, _) = machine(">+.+++++++++.", "", config);
+            let output = FlushLog::default();
+            machine.set_output(output.clone());
+            machine.run_for(12);
+            output.flushes()
+        };
+        assert_eq!(flushes_with(FlushPolicy::EveryByte), vec![1, 2]);
+        assert_eq!(flushes_with(FlushPolicy::OnNewline), vec![2]);
+        assert!(flushes_with(FlushPolicy::OnTermination).is_empty());
+
+        for flush_policy in [FlushPolicy::EveryByte, FlushPolicy::OnNewline] {
+            let config = Config::default().with_flush_policy(flush_policy);
+            let (machine, _) = machine(">+.", "", config);
+            let loaded = Machine::load(save(&machine).as_slice()).expect("the savefile loads");
+            assert_eq!(loaded.config.flush_policy, flush_policy);
+        }
+    }
+
+    #[test]
+    fn not_complements_every_bit_of_the_cell() {
+        let config = Config::default().with_initial_lattice(vec![0, 0x0f0f_0000]);
+        let (mut once, _) = machine(">I", "", config.clone());
+        once.run();
+        assert_eq!(once.vessel().data_lattice()[1], 0xf0f0_ffff);
+
+        let (mut twice, _) = machine(">II", "", config);
+        twice.run();
+        assert_eq!(twice.vessel().data_lattice()[1], 0x0f0f_0000);
+    }
+
+    #[test]
+    fn incrementing_a_full_cell_wraps_instead_of_panicking() {
+        let (termination, output) = run(">I+:", "", Config::default());
+        assert_eq!(termination, Termination::NoSignal(3, 0));
+        assert!(output.is_empty());
+    }
+
+    #[test]
+    fn shifts_drop_the_bits_that_fall_off_either_end() {
+        let shifted = |source: &str| {
+            let config = Config::default().with_initial_lattice(vec![0, 0x8000_0003]);
+            let (mut machine, _) = machine(source, "", config);
+            machine.run();
+            machine.vessel().data_lattice()[1]
+        };
+        assert_eq!(shifted(">l"), 6);
+        assert_eq!(shifted(">lrr"), 1);
+        assert_eq!(shifted(">r"), 0x4000_0001);
+    }
+
+    #[test]
+    fn a_savefile_keeps_the_run_limits() {
+        let config = Config::default()
+            .with_max_steps(50)
+            .with_timeout(Duration::from_millis(1_500))
+            .with_output_limit(2);
+        let (mut original, _) = machine(">+.v\n^  <", "", config);
+        original.run_for(2);
+        let mut loaded = Machine::load(save(&original).as_slice()).unwrap();
+        assert_eq!(loaded.config.max_steps, Some(50));
+        assert_eq!(loaded.config.timeout, Some(Duration::from_millis(1_500)));
+        assert_eq!(loaded.config.output_limit, Some(2));
+        loaded.set_output(io::sink());
+        assert_eq!(loaded.run().termination(), Termination::OutputLimit);
+
+        // A timeout too long to count in nanoseconds is kept as the longest one that is.
+        let config = Config::default().with_timeout(Duration::MAX);
+        let loaded = Machine::load(save(&machine(">+", "", config).0).as_slice()).unwrap();
+        assert_eq!(loaded.config.timeout, Some(Duration::from_nanos(u64::MAX)));
+
+        // A Vessel refused a cell past the limit stays refused once restored.
+        let config = Config::default().with_lattice_limit(16);
+        let (mut original, _) = machine(">&}+ ", "100", config);
+        assert_eq!(original.run().termination(), Termination::LatticeLimit);
+        assert!(original.vessel().lattice_overflowed());
+        let mut loaded = Machine::load(save(&original).as_slice()).unwrap();
+        assert!(loaded.vessel().lattice_overflowed());
+        assert_eq!(loaded.run().termination(), Termination::LatticeLimit);
+    }
+
+    #[test]
+    fn park_if_zero_parks_only_on_an_empty_cell() {
+        assert_eq!(run(">> p!", "", Config::default()).1, b"1");
+        assert_eq!(run(">> + p !", "", Config::default()).1, b"2");
+    }
+
+    #[test]
+    fn output_string_prints_the_indexed_table_entry() {
+        let table = vec!["hello".to_string(), "wörld\n".to_string()];
+        let (mut original, output) = machine(">\"+\"+\"", "", Config::default());
+        original.set_string_table(table.clone());
+        original.run();
+        assert_eq!(output.contents(), "hellowörld\n".as_bytes());
+
+        let (mut original, _) = machine(">\"+\"", "", Config::default());
+        original.set_string_table(table);
+        let mut loaded = Machine::load(save(&original).as_slice()).unwrap();
+        let output = SharedBuffer::default();
+        loaded.set_output(output.clone());
+        loaded.run();
+        assert_eq!(output.contents(), "hellowörld\n".as_bytes());
+    }
+
+    #[test]
+    fn state_keys_ignore_the_lattice() {
+        let key_after_two_steps = |source| {
+            let (mut state, _) = machine(source, "", Config::default());
+            state.run_for(2);
+            state.vessel().state_key()
+        };
+        let key = key_after_two_steps(">+  ");
+        let expected = VesselState {
+            x: 2,
+            y: 0,
+            direction: Direction::Right,
+            velocity: 1,
+        };
+        assert_eq!(key, expected);
+
+        let mut seen = HashSet::from([key]);
+        assert!(!seen.insert(key_after_two_steps(">   ")));
+        assert!(seen.insert(key_after_two_steps(">>  ")));
+    }
+
+    #[test]
+    fn slurp_input_reads_everything_after_the_current_cell() {
+        let (mut slurping, _) = machine(">B", "hi\n", Config::default());
+        slurping.run();
+        assert_eq!(
+            slurping.vessel().data_lattice()[..5],
+            [0, 3, b'h' as u32, b'i' as u32, b'\n' as u32]
+        );
+
+        let (mut slurping, _) = machine(">BB", "hi\n", Config::default());
+        slurping.run();
+        assert_eq!(slurping.vessel().data_lattice()[1], 0);
+    }
+
+    #[test]
+    fn dup_forward_copies_the_current_cell_into_the_next() {
+        let dup = |cells| {
+            let config = Config::default().with_initial_lattice(cells);
+            let (mut copying, _) = machine(">=", "", config);
+            copying.run();
+            copying.vessel().data_lattice()[..3].to_vec()
+        };
+        assert_eq!(dup(vec![0, 5, 9]), [0, 5, 5]);
+        // The next cell is allocated when it does not exist yet.
+        assert_eq!(dup(vec![0, 6]), [0, 6, 6]);
+    }
+
+    #[test]
+    fn stop_if_zero_halts_only_on_an_empty_cell() {
+        assert_eq!(
+            run(">Z+.", "", Config::default()),
+            (Termination::Stopped, vec![])
+        );
+        assert_eq!(
+            run(">+Z.", "", Config::default()),
+            (Termination::NoSignal(3, 0), vec![1])
+        );
+    }
+
+    #[test]
+    fn warnings_and_debug_lines_go_to_their_own_sinks() {
+        let config = Config::new(true, false, false);
+        let (mut noisy, output) = machine(">I.D", "", config);
+        let diagnostics = SharedBuffer::default();
+        let trace = SharedBuffer::default();
+        noisy.set_diagnostic_output(diagnostics.clone());
+        noisy.set_trace_output(trace.clone());
+        noisy.run();
+
+        assert!(output.contents().is_empty());
+        assert_eq!(
+            String::from_utf8(diagnostics.contents()).unwrap(),
+            format!(
+                "Velo Warning: Cannot output valid ASCII value: {}\n",
+                u32::MAX
+            )
+        );
+        let trace = String::from_utf8(trace.contents()).unwrap();
+        assert_eq!(trace.lines().count(), 1);
+        assert!(
+            trace.starts_with("[Debug] Vessel: Vessel { x: 3, y: 0,"),
+            "{}",
+            trace
+        );
+    }
+
+    #[test]
+    fn random_bytes_repeat_for_the_same_seed() {
+        let bytes = |seed| {
+            let config = Config::default()
+                .with_seed(seed)
+                .with_output_encoding(OutputEncoding::Latin1);
+            run(&format!(">{}", "b.".repeat(64)), "", config).1
+        };
+        let first = bytes(7);
+        assert_eq!(first.len(), 64);
+        assert_eq!(first, bytes(7));
+        assert_ne!(first, bytes(8));
+    }
+
+    #[test]
+    fn flush_input_discards_the_rest_of_the_line() {
+        assert_eq!(run(">_,.", "skip me\nab", Config::default()).1, b"a");
+
+        // Without a newline everything left is discarded.
+        let (mut flushing, _) = machine(">__B", "skip me\nab", Config::default());
+        flushing.run();
+        assert_eq!(flushing.vessel().data_lattice()[1], 0);
+    }
+
+    #[test]
+    fn output_hex_prints_at_least_two_lowercase_digits() {
+        let hex = |cell| {
+            let config = Config::default().with_initial_lattice(vec![0, cell]);
+            run(">x", "", config).1
+        };
+        assert_eq!(hex(7), b"07");
+        assert_eq!(hex(255), b"ff");
+        assert_eq!(hex(0x1f00), b"1f00");
+    }
+
+    #[test]
+    fn the_register_copies_pastes_and_compares_cells() {
+        let lattice = || Config::default().with_initial_lattice(vec![0, 65, 66]);
+
+        // Nothing copied yet pastes 0.
+        let (mut pasting, _) = machine(">g", "", lattice());
+        pasting.run();
+        assert_eq!(pasting.vessel().data_lattice()[1], 0);
+
+        assert_eq!(run(">ce", "", lattice()).1, b"A");
+        assert_eq!(run(">c+e", "", lattice()).1, b"");
+        assert_eq!(run(">c+g.", "", lattice()).1, b"A");
+
+        // The register survives a save and load.
+        let (mut original, _) = machine(">c e", "", lattice());
+        original.run_for(2);
+        let mut loaded = Machine::load(save(&original).as_slice()).unwrap();
+        assert_eq!(loaded.vessel().register(), Some(65));
+        let output = SharedBuffer::default();
+        loaded.set_output(output.clone());
+        loaded.run();
+        assert_eq!(output.contents(), b"A");
+    }
+
+    #[test]
+    fn poll_step_waits_for_fed_input_instead_of_blocking() {
+        let (mut polled, output) = machine(">,.,.", "", Config::default());
+        assert_eq!(polled.poll_step(), StepOutcome::NeedsInput);
+        assert_eq!(polled.poll_step(), StepOutcome::NeedsInput);
+        assert_eq!(polled.steps(), 0);
+
+        polled.feed_input(b"a");
+        assert_eq!(polled.poll_step(), StepOutcome::Running);
+        assert_eq!(polled.poll_step(), StepOutcome::Output(b"a".to_vec()));
+        assert_eq!(polled.poll_step(), StepOutcome::NeedsInput);
+        polled.close_input();
+        assert_eq!(polled.poll_step(), StepOutcome::Running);
+        assert_eq!(polled.poll_step(), StepOutcome::Output(vec![0]));
+        assert_eq!(
+            polled.poll_step(),
+            StepOutcome::Terminated(Termination::NoSignal(4, 0))
+        );
+        assert_eq!(output.contents(), [b'a', 0]);
+    }
+
+    #[test]
+    fn poll_step_waits_for_a_whole_line_number_or_input() {
+        let after_feeding = |source, fed: &[u8]| {
+            let (mut polled, _) = machine(source, "", Config::default());
+            polled.feed_input(fed);
+            polled.poll_step()
+        };
+        assert_eq!(after_feeding(">&", b" 12"), StepOutcome::NeedsInput);
+        assert_eq!(after_feeding(">&", b" 12 "), StepOutcome::Running);
+        assert_eq!(after_feeding(">C", b"ab"), StepOutcome::NeedsInput);
+        assert_eq!(after_feeding(">C", b"ab\n"), StepOutcome::Running);
+        assert_eq!(after_feeding(">B", b"ab\n"), StepOutcome::NeedsInput);
+    }
+
+    #[test]
+    fn a_fleet_resumes_its_cycle_where_input_ran_out() {
+        // Both Vessels read in the second cycle: the original first, then the spawned one.
+        let source = ">*,.\n ,\n .";
+        assert_eq!(run(source, "ab", Config::default()).1, b"ab");
+
+        let (mut polled, output) = machine(source, "", Config::default());
+        polled.feed_input(b"a");
+        while polled.poll_step() != StepOutcome::NeedsInput {}
+        assert_eq!(polled.steps(), 2);
+
+        let mut loaded = Machine::load(save(&polled).as_slice()).unwrap();
+        let resumed = SharedBuffer::default();
+        loaded.set_output(resumed.clone());
+        for (host, printed) in [(&mut polled, &output), (&mut loaded, &resumed)] {
+            host.feed_input(b"b");
+            host.close_input();
+            while !matches!(host.poll_step(), StepOutcome::Terminated(_)) {}
+            assert_eq!(printed.contents(), b"ab");
+        }
+    }
+
+    #[test]
+    fn goto_label_jumps_back_to_the_matching_set_label() {
+        let config = || Config::default().with_max_steps(20);
+        let (termination, output) = run(">m+.-G", "", config());
+        assert_eq!(termination, Termination::StepLimit);
+        assert!(output.len() >= 4 && output.iter().all(|&byte| byte == 1));
+
+        // A label that was never set leaves the Vessel where it is.
+        let (termination, output) = run(">m+.G", "", Config::default());
+        assert_eq!(termination, Termination::NoSignal(4, 0));
+        assert_eq!(output, [1]);
+
+        // Recorded labels survive a save and load.
+        let (mut original, _) = machine(">m+.-G", "", config());
+        original.run_for(2);
+        let mut loaded = Machine::load(save(&original).as_slice()).unwrap();
+        let output = SharedBuffer::default();
+        loaded.set_output(output.clone());
+        assert_eq!(loaded.run().termination(), Termination::StepLimit);
+        assert!(output.contents().len() >= 4);
+    }
+
+    #[test]
+    fn store_distance_counts_the_cells_moved_through() {
+        let (termination, output) = run(">+  d.", "", Config::default());
+        assert_eq!(termination, Termination::NoSignal(5, 0));
+        assert_eq!(output, [4]);
+
+        // Skipped Void runes still count as cells moved through.
+        let config = Config::default().with_skip_void(true);
+        let (mut gliding, output) = machine(">+  d.", "", config);
+        gliding.run();
+        assert_eq!(gliding.vessel().distance_traveled(), 5);
+        assert_eq!(output.contents(), [4]);
+
+        // The count carries over a save and load.
+        let (mut original, _) = machine(">+  d.", "", Config::default());
+        original.run_for(2);
+        let mut loaded = Machine::load(save(&original).as_slice()).unwrap();
+        let output = SharedBuffer::default();
+        loaded.set_output(output.clone());
+        loaded.run();
+        assert_eq!(output.contents(), [4]);
+    }
+
+    #[test]
+    fn output_repeated_prints_the_cell_as_many_times_as_the_next_cell_says() {
+        let repeated =
+            |cells: Vec<u32>, config: Config| run(">o", "", config.with_initial_lattice(cells)).1;
+        assert_eq!(
+            repeated(vec![0, u32::from(b'z'), 3], Config::default()),
+            b"zzz"
+        );
+        assert_eq!(
+            repeated(vec![0, u32::from(b'y'), 0], Config::default()),
+            b""
+        );
+
+        // A huge count is capped, and stops early at the output limit.
+        let huge = || vec![0, u32::from(b'x'), u32::MAX];
+        assert_eq!(
+            repeated(huge(), Config::default()).len(),
+            MAX_OUTPUT_REPEAT as usize
+        );
+        let limited = Config::default().with_output_limit(10);
+        assert_eq!(repeated(huge(), limited).len(), 11);
+    }
+
+    #[test]
+    fn a_zero_based_pointer_starts_on_cell_zero() {
+        let config = Config::default().with_initial_lattice(vec![u32::from(b'A'), u32::from(b'B')]);
+        assert_eq!(run(">.", "", config.clone()).1, b"B");
+        let config = config.with_zero_based_pointer(true);
+        assert_eq!(run(">.", "", config.clone()).1, b"A");
+        // Runes using the next cell use cell 1.
+        let (mut duplicating, _) = machine(">=", "", config.clone());
+        duplicating.run();
+        assert_eq!(duplicating.vessel().data_lattice()[..2], [65, 65]);
+
+        // The savefile keeps the setting.
+        let (original, _) = machine(">+.", "", config);
+        let mut loaded = Machine::load(save(&original).as_slice()).unwrap();
+        let output = SharedBuffer::default();
+        loaded.set_output(output.clone());
+        loaded.run();
+        assert_eq!(output.contents(), b"B");
+    }
+}
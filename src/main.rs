@@ -1,15 +1,31 @@
+use std::fs::File;
+use std::io::{BufReader, BufWriter, IsTerminal, Write};
+use std::path::Path;
 use std::process::ExitCode;
-use std::{fs, io};
+use std::time::Duration;
+use std::{fs, io, thread};
 
-use velo::models::{Cosmos, Rune, Vessel};
-use velo::sail::{Config, Termination, sail};
+use velo::include::{expand_includes, include_target, read_source};
+use velo::machine::{Machine, StepOutcome};
+use velo::models::{Cosmos, Rune};
+use velo::parse::{ParseError, ParseOptions, ProgramMeta};
+use velo::sail::{
+    CompletionReport, Config, EdgePolicy, FlushPolicy, OutputEncoding, SETUP_FAILURE, SailError,
+    Termination, TraceFormat,
+};
+use velo::svg::{MAX_PATH_POINTS, path_to_svg};
 
-use clap::Parser;
+use clap::{Parser, ValueEnum};
 
 #[derive(Parser)]
 #[command(version)]
 struct Args {
-    file_path: String,
+    #[arg(required_unless_present = "runes")]
+    file_path: Option<String>,
+
+    /// Print every rune and its meaning, then exit.
+    #[arg(long)]
+    runes: bool,
 
     #[arg(short, long)]
     debug: bool,
@@ -19,86 +35,839 @@ struct Args {
 
     #[arg(long, requires = "trace")]
     ignore_void: bool,
+
+    /// Trace line layout: "debug" or "table".
+    #[arg(long, requires = "trace", default_value = "debug")]
+    trace_format: TraceFormat,
+
+    /// Write trace lines to PATH instead of stdout.
+    #[arg(long, requires = "trace", value_name = "PATH")]
+    trace_file: Option<String>,
+
+    /// Draw the grid and the path the vessel took as an SVG image at PATH.
+    #[arg(long, conflicts_with = "animate", value_name = "PATH")]
+    svg: Option<String>,
+
+    /// How `.` writes cells: "utf8", "latin1" or "raw-low-byte".
+    #[arg(long, value_name = "ENCODING", default_value = "utf8")]
+    output_encoding: OutputEncoding,
+
+    /// When output is flushed: "every-byte", "newline" or "termination".
+    #[arg(long, value_name = "POLICY", default_value = "termination")]
+    flush: FlushPolicy,
+
+    /// Show positions in trace and debug output as 1-based line:col.
+    #[arg(long)]
+    line_col: bool,
+
+    /// Mark the cell at the pointer in trace and debug lattice dumps.
+    #[arg(long)]
+    show_pointer: bool,
+
+    /// Cross runs of Void runes in a single cycle.
+    #[arg(long)]
+    no_exec_on_void: bool,
+
+    /// What happens at the edge of the grid: "terminate", "wrap" or "bounce".
+    #[arg(long, value_name = "POLICY", default_value = "terminate")]
+    edge_policy: EdgePolicy,
+
+    /// Move VELOCITY cells per cycle, impacting only the landing cell.
+    #[arg(long)]
+    velocity_is_step: bool,
+
+    /// Reset velocity to 1 whenever a perpendicular thrust turns the vessel.
+    #[arg(long)]
+    reset_velocity_on_turn: bool,
+
+    /// Make the pointer address cell VELOCITY - 1, so it starts at cell 0.
+    #[arg(long)]
+    zero_based_pointer: bool,
+
+    /// Pause MS milliseconds after every cycle, to watch the program run.
+    #[arg(long, value_name = "MS", default_value_t = 0)]
+    step_delay: u64,
+
+    /// Redraw the grid with the vessel highlighted every cycle. Ignored unless stdout is a terminal.
+    #[arg(long)]
+    animate: bool,
+
+    /// Use a fixed lattice of SIZE cells; the data pointer wraps around it.
+    #[arg(long, value_name = "SIZE")]
+    ring_lattice: Option<usize>,
+
+    /// Stop once the vessel has bounced between two cells for CYCLES cycles without progress.
+    #[arg(long, value_name = "CYCLES")]
+    detect_oscillation: Option<u64>,
+
+    /// Stop once the vessel needs more than CELLS data cells.
+    #[arg(long, value_name = "CELLS")]
+    lattice_limit: Option<usize>,
+
+    /// Stop after STEPS cycles.
+    #[arg(long, value_name = "STEPS")]
+    max_steps: Option<u64>,
+
+    /// Stop once the run has taken MS milliseconds.
+    #[arg(long, value_name = "MS")]
+    timeout: Option<u64>,
+
+    /// Stop once the program has written more than BYTES bytes of output.
+    #[arg(long, value_name = "BYTES")]
+    max_output: Option<u64>,
+
+    /// Comma-separated initial data cells, starting at cell 0 (e.g. "0,72,105").
+    #[arg(long, value_name = "CELLS", value_delimiter = ',')]
+    lattice: Option<Vec<u32>>,
+
+    /// Read program input from PATH instead of stdin.
+    #[arg(long, value_name = "PATH")]
+    input_file: Option<String>,
+
+    /// Load the string table printed by `"` from PATH, one entry per line.
+    #[arg(long, value_name = "PATH")]
+    strings: Option<String>,
+
+    /// Seed for the random Runes. Omit to seed from OS entropy.
+    #[arg(long)]
+    seed: Option<u64>,
+
+    /// Print the program in canonical form instead of running it.
+    #[arg(long)]
+    fmt: bool,
+
+    /// With --fmt, write the canonical form back to the file.
+    #[arg(long, requires = "fmt")]
+    in_place: bool,
+
+    /// Check the program for common mistakes instead of running it.
+    #[arg(long, conflicts_with = "fmt")]
+    check: bool,
+
+    /// Print the program's control-flow graph in DOT format instead of running it.
+    #[arg(long, conflicts_with_all = ["fmt", "check"])]
+    cfg: bool,
+
+    /// Write the program as a binary grid (.velob) to PATH instead of running it.
+    #[arg(long, value_name = "PATH", conflicts_with_all = ["fmt", "check", "cfg"])]
+    write_binary: Option<String>,
+
+    /// After the run, write a machine-readable summary to stderr instead of the usual message.
+    #[arg(long, value_name = "FORMAT")]
+    report: Option<ReportFormat>,
+
+    /// After the run, list the runes no vessel impacted on stderr.
+    #[arg(long)]
+    report_unused: bool,
+
+    /// Swap rows and columns of the program before running it. The W, J, * and
+    /// R runes keep turning the same way, so programs using them change meaning.
+    #[arg(long, conflicts_with = "fmt")]
+    transpose: bool,
+
+    /// Refuse programs whose grid has more than CELLS cells.
+    #[arg(long, value_name = "CELLS")]
+    max_grid_cells: Option<usize>,
+
+    /// Reject programs with non-ASCII characters outside comments.
+    #[arg(long)]
+    ascii_only: bool,
+
+    /// Count comments toward the grid width, so the grid matches the source's layout.
+    #[arg(long, conflicts_with = "fmt")]
+    comments_in_width: bool,
+
+    /// Characters that start a comment, e.g. "#;".
+    #[arg(long, default_value = "#")]
+    comment_chars: String,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum ReportFormat {
+    Json,
 }
 
 fn main() -> ExitCode {
-    let cli = Args::parse();
+    let cli = match Args::try_parse() {
+        Ok(cli) => cli,
+        // `--help` and `--version` end up here too, and succeed.
+        Err(error) => {
+            let _ = error.print();
+            return if error.use_stderr() {
+                ExitCode::from(SETUP_FAILURE)
+            } else {
+                ExitCode::SUCCESS
+            };
+        }
+    };
+
+    if cli.runes {
+        print_rune_legend();
+        return ExitCode::SUCCESS;
+    }
+    let file_path = cli.file_path.clone().unwrap_or_default();
 
-    let config = Config::new(cli.debug || cli.trace, cli.trace, cli.ignore_void);
+    let mut config = Config::new(cli.debug || cli.trace, cli.trace, cli.ignore_void)
+        .with_skip_void(cli.no_exec_on_void)
+        .with_velocity_is_step(cli.velocity_is_step)
+        .with_edge_policy(cli.edge_policy)
+        .with_reset_velocity_on_turn(cli.reset_velocity_on_turn)
+        .with_zero_based_pointer(cli.zero_based_pointer)
+        .with_step_delay(Duration::from_millis(cli.step_delay))
+        .with_trace_format(cli.trace_format)
+        .with_output_encoding(cli.output_encoding)
+        .with_flush_policy(cli.flush)
+        .with_line_col(cli.line_col)
+        .with_show_pointer(cli.show_pointer)
+        .with_unused_tracking(cli.report_unused);
+    if let Some(seed) = cli.seed {
+        config = config.with_seed(seed);
+    }
+    if let Some(size) = cli.ring_lattice {
+        config = config.with_ring_lattice(size);
+    }
+    if let Some(cells) = cli.lattice.clone() {
+        config = config.with_initial_lattice(cells);
+    }
+    if let Some(cycles) = cli.detect_oscillation {
+        config = config.with_oscillation_detection(cycles);
+    }
+    if let Some(cells) = cli.lattice_limit {
+        config = config.with_lattice_limit(cells);
+    }
+    if let Some(steps) = cli.max_steps {
+        config = config.with_max_steps(steps);
+    }
+    if let Some(ms) = cli.timeout {
+        config = config.with_timeout(Duration::from_millis(ms));
+    }
+    if let Some(bytes) = cli.max_output {
+        config = config.with_output_limit(bytes);
+    }
+    let mut parse_options = ParseOptions::default()
+        .with_comment_chars(cli.comment_chars.chars())
+        .with_transpose(cli.transpose)
+        .with_ascii_only(cli.ascii_only)
+        .with_comments_in_width(cli.comments_in_width);
+    if let Some(limit) = cli.max_grid_cells {
+        parse_options = parse_options.with_max_grid_cells(limit);
+    }
 
-    match load_velo_code(&cli.file_path) {
+    match load_velo_program(&file_path, &parse_options) {
         Err(msg) => {
             eprintln!("Failed to load velo file. {:}", msg);
-            ExitCode::FAILURE
+            ExitCode::from(SETUP_FAILURE)
         }
-        Ok(code) => {
-            let code_lines: Vec<String> = code.lines().map(|line| line.to_string()).collect();
+        Ok(parsed) => {
+            if cli.fmt || cli.check || cli.cfg || cli.write_binary.is_some() {
+                if cli.in_place && file_path.ends_with(BINARY_EXTENSION) {
+                    eprintln!("A binary grid cannot be formatted in place.");
+                    return ExitCode::from(SETUP_FAILURE);
+                }
+                if cli.in_place && has_includes(&file_path) {
+                    eprintln!("A program with !include directives cannot be formatted in place.");
+                    return ExitCode::from(SETUP_FAILURE);
+                }
+                return match parsed {
+                    Ok((cosmos, meta)) if cli.fmt => {
+                        // Without a comment character the header cannot be written back.
+                        let header = parse_options
+                            .comment_chars()
+                            .first()
+                            .map(|&comment_char| meta.header(comment_char))
+                            .unwrap_or_default();
+                        let comments = load_comments(&file_path, &parse_options);
+                        format_velo_code(&cosmos, &header, &comments, &file_path, cli.in_place)
+                    }
+                    Ok((cosmos, _)) if cli.cfg => {
+                        print!("{}", cosmos.control_flow_graph().to_dot());
+                        ExitCode::SUCCESS
+                    }
+                    Ok((cosmos, _)) => match cli.write_binary.as_deref() {
+                        Some(path) => write_velo_binary(&cosmos, path),
+                        None => check_velo_code(cosmos),
+                    },
+                    Err(error) => {
+                        eprintln!("{:}", error);
+                        SailError::from(error).exit_code()
+                    }
+                };
+            }
 
-            let cosmos = materialize_runes(code_lines);
+            let input = match cli.input_file.as_deref().map(File::open) {
+                Some(Err(msg)) => {
+                    eprintln!("Failed to open input file. {:}", msg);
+                    return ExitCode::from(SETUP_FAILURE);
+                }
+                Some(Ok(file)) => Some(BufReader::new(file)),
+                None => None,
+            };
+            let strings = match cli.strings.as_deref().map(fs::read_to_string) {
+                Some(Err(msg)) => {
+                    eprintln!("Failed to load string table. {:}", msg);
+                    return ExitCode::from(SETUP_FAILURE);
+                }
+                Some(Ok(table)) => table.lines().map(String::from).collect(),
+                None => Vec::new(),
+            };
+            let trace = match cli.trace_file.as_deref().map(File::create) {
+                Some(Err(msg)) => {
+                    eprintln!("Failed to create trace file. {:}", msg);
+                    return ExitCode::from(SETUP_FAILURE);
+                }
+                Some(Ok(file)) => Some(BufWriter::new(file)),
+                None => None,
+            };
+            let svg = match cli.svg.as_deref().map(File::create) {
+                Some(Err(msg)) => {
+                    eprintln!("Failed to create SVG file. {:}", msg);
+                    return ExitCode::from(SETUP_FAILURE);
+                }
+                Some(Ok(file)) => Some(BufWriter::new(file)),
+                None => None,
+            };
+            let animation_delay = (cli.animate && io::stdout().is_terminal())
+                .then(|| Duration::from_millis(cli.step_delay));
 
-            let start_rune = cosmos.get(0, 0);
+            let result = parsed.map_err(SailError::from).and_then(|(cosmos, meta)| {
+                if parse_options.transpose() {
+                    warn_turns_in_transpose(&cosmos);
+                }
+                let config = with_program_meta(config, &cli, &meta);
+                run_velo_code(cosmos, config, input, strings, trace, svg, animation_delay)
+            });
 
-            let vessel = Vessel::new(0, 0, start_rune);
+            if let Some(ReportFormat::Json) = cli.report {
+                match &result {
+                    Ok(report) => eprintln!("{}", report.to_json()),
+                    Err(error) => eprintln!("{}", error.to_json()),
+                }
+                return match result {
+                    Ok(report) => report.termination().exit_code(),
+                    Err(error) => error.exit_code(),
+                };
+            }
 
-            match sail(cosmos, vessel, config) {
-                Termination::Stopped => ExitCode::SUCCESS,
-                Termination::NoSignal(last_signal_x, last_signal_y) => {
-                    eprintln!(
-                        "The vessel traveled out of the cosmos. Last signal coordinate: {{ x: {:}, y: {:} }}",
-                        last_signal_x, last_signal_y
-                    );
-                    ExitCode::FAILURE
+            match result {
+                Ok(report) => {
+                    match report.termination() {
+                        Termination::NoSignal(last_signal_x, last_signal_y) => eprintln!(
+                            "The vessel traveled out of the cosmos. Last signal coordinate: {{ x: {:}, y: {:} }}",
+                            last_signal_x, last_signal_y
+                        ),
+                        Termination::StuckOscillation => {
+                            eprintln!("The vessel is stuck bouncing between two cells.")
+                        }
+                        Termination::LatticeLimit => {
+                            eprintln!("The vessel needed more data cells than the limit allows.")
+                        }
+                        Termination::StepLimit => {
+                            eprintln!("The program ran for {} cycles, the limit.", report.steps())
+                        }
+                        Termination::Timeout => eprintln!("The program ran out of time."),
+                        Termination::OutputLimit => {
+                            eprintln!("The program wrote more output than the limit allows.")
+                        }
+                        _ => (),
+                    }
+                    report.termination().exit_code()
                 }
-                Termination::NoInitialVelocityOrDirection => {
-                    eprintln!("Here was no Thrust rune at the top left corner of the cosmos.");
-                    ExitCode::FAILURE
+                Err(error) => {
+                    eprintln!("{:}", error);
+                    error.exit_code()
                 }
             }
         }
     }
 }
 
-fn load_velo_code(path: &str) -> io::Result<String> {
-    let content = fs::read_to_string(path)?;
+fn print_rune_legend() {
+    for rune in Rune::ALL {
+        println!(
+            "{}  {:<20} {}",
+            rune.to_char(),
+            rune.name(),
+            rune.description()
+        );
+    }
+}
+
+// Largest part of the grid drawn by `--animate`; bigger grids scroll with the vessel.
+const ANIMATION_WIDTH: usize = 78;
+const ANIMATION_HEIGHT: usize = 20;
+
+/// Runs the program, reading `input` instead of stdin and writing trace lines
+/// to `trace` instead of stdout when given.
+fn run_velo_code(
+    cosmos: Cosmos,
+    config: Config,
+    input: Option<BufReader<File>>,
+    strings: Vec<String>,
+    trace: Option<BufWriter<File>>,
+    svg: Option<BufWriter<File>>,
+    animation_delay: Option<Duration>,
+) -> Result<CompletionReport, SailError> {
+    let mut machine = Machine::new_from(&cosmos, config)?;
+    if let Some(input) = input {
+        machine.set_input(input);
+    }
+    if let Some(trace) = trace {
+        machine.set_trace_output(trace);
+    }
+    machine.set_string_table(strings);
+
+    let report = match animation_delay {
+        Some(delay) => animate(&mut machine, delay),
+        None => match svg {
+            Some(svg) => draw_path(&mut machine, svg),
+            None => machine.run(),
+        },
+    };
+
+    // Empty unless --report-unused enabled the tracking.
+    for (x, y, rune) in machine.unused_runes() {
+        eprintln!(
+            "Unused rune `{}` ({}) at {{ x: {:}, y: {:} }}",
+            rune.to_char(),
+            rune.name(),
+            x,
+            y
+        );
+    }
+
+    Ok(report)
+}
+
+/// Warns about each kind of Rune whose turns a transposed grid does not mirror.
+fn warn_turns_in_transpose(cosmos: &Cosmos) {
+    for rune in Rune::ALL {
+        let used =
+            (0..cosmos.height()).any(|y| (0..cosmos.width()).any(|x| cosmos.get(x, y) == rune));
+        if rune.turns_in_transpose() && used {
+            eprintln!(
+                "Velo Warning: `{}` ({}) turns the same way in the transposed grid, so the program behaves differently.",
+                rune.to_char(),
+                rune.name()
+            );
+        }
+    }
+}
+
+/// Runs the program, then draws the cells the vessel passed through. Only
+/// the first `MAX_PATH_POINTS` cells are drawn, so a long run cannot grow
+/// the path without bound.
+fn draw_path(machine: &mut Machine, mut svg: BufWriter<File>) -> CompletionReport {
+    let mut path = vec![(machine.vessel().x(), machine.vessel().y())];
+    let mut cut_short = false;
+    let report = loop {
+        machine.step();
+        let position = (machine.vessel().x(), machine.vessel().y());
+        if path.last() != Some(&position) {
+            if path.len() < MAX_PATH_POINTS {
+                path.push(position);
+            } else {
+                cut_short = true;
+            }
+        }
+        if let Some(report) = machine.report() {
+            break report;
+        }
+    };
+
+    if cut_short {
+        eprintln!(
+            "Velo Warning: The SVG image shows only the first {} cells of the path.",
+            MAX_PATH_POINTS
+        );
+    }
+    let drawn = svg
+        .write_all(path_to_svg(&path, machine.cosmos()).as_bytes())
+        .and_then(|()| svg.flush());
+    if let Err(msg) = drawn {
+        eprintln!("Failed to write SVG file. {:}", msg);
+    }
+    report
+}
+
+/// Runs the program, redrawing the grid around the vessel after every cycle.
+fn animate(machine: &mut Machine, delay: Duration) -> CompletionReport {
+    // Program output is drawn beneath the grid instead of interleaved with it.
+    machine.set_output(io::sink());
+    let mut output = Vec::new();
+
+    loop {
+        let outcome = machine.step();
+        if let StepOutcome::Output(bytes) = &outcome {
+            output.extend_from_slice(bytes);
+        }
+        draw_frame(machine, &output);
 
-    Ok(content)
+        if let Some(report) = machine.report() {
+            return report;
+        }
+        thread::sleep(delay);
+    }
 }
 
-fn materialize_runes(lines: Vec<String>) -> Cosmos {
-    let runes: Vec<Vec<Rune>> = lines
-        .iter()
-        .map(|line| {
-            if let Some((before_hash, _)) = line.split_once('#') {
-                before_hash.to_string()
+fn draw_frame(machine: &Machine, output: &[u8]) {
+    let cosmos = machine.cosmos();
+    let vessel = machine.vessel();
+    let left = window_start(vessel.x(), cosmos.width(), ANIMATION_WIDTH);
+    let top = window_start(vessel.y(), cosmos.height(), ANIMATION_HEIGHT);
+
+    // Clear the screen and move the cursor home.
+    let mut frame = String::from("\x1b[2J\x1b[H");
+    for y in top..(top + ANIMATION_HEIGHT).min(cosmos.height()) {
+        for x in left..(left + ANIMATION_WIDTH).min(cosmos.width()) {
+            let c = cosmos.get(x, y).to_char();
+            if (x, y) == (vessel.x(), vessel.y()) {
+                // Reverse video marks the vessel.
+                frame.push_str(&format!("\x1b[7m{}\x1b[0m", c));
             } else {
-                line.clone()
+                frame.push(c);
+            }
+        }
+        frame.push('\n');
+    }
+    frame.push_str(&format!(
+        "\nstep {}  x {}  y {}  velocity {}\n\n",
+        machine.steps(),
+        vessel.x(),
+        vessel.y(),
+        vessel.velocity()
+    ));
+    frame.push_str(&String::from_utf8_lossy(output));
+
+    let mut stdout = io::stdout();
+    let _ = stdout.write_all(frame.as_bytes());
+    let _ = stdout.flush();
+}
+
+/// First index of a `window`-long view of `len` cells that keeps `position` near its middle.
+fn window_start(position: usize, len: usize, window: usize) -> usize {
+    position
+        .saturating_sub(window / 2)
+        .min(len.saturating_sub(window))
+}
+
+// Programs in files with this extension are binary grids rather than source text.
+const BINARY_EXTENSION: &str = ".velob";
+
+/// Loads a program, reading binary grids directly and parsing anything else as source text.
+fn load_velo_program(
+    path: &str,
+    options: &ParseOptions,
+) -> io::Result<Result<(Cosmos, ProgramMeta), ParseError>> {
+    if path.ends_with(BINARY_EXTENSION) {
+        let cosmos = Cosmos::read_binary(BufReader::new(File::open(path)?))?;
+        if let Some(limit) = options.max_grid_cells() {
+            let cells = cosmos.width().saturating_mul(cosmos.height());
+            if cells > limit {
+                return Ok(Err(ParseError::TooLarge { cells, limit }));
             }
-            .chars()
-            .map(|c| char_to_rune(c))
-            .collect()
+        }
+        // A binary grid holds no text, so only the size and layout options apply.
+        let cosmos = if options.transpose() {
+            cosmos.transpose()
+        } else {
+            cosmos
+        };
+        return Ok(Ok((cosmos, ProgramMeta::default())));
+    }
+
+    let code = load_velo_code(path)?;
+    Ok(Cosmos::parse_with_meta(&code, options))
+}
+
+/// Applies the settings of the program's metadata header that were not given
+/// on the command line.
+fn with_program_meta(mut config: Config, cli: &Args, meta: &ProgramMeta) -> Config {
+    if cli.seed.is_none()
+        && let Some(seed) = meta.seed()
+    {
+        config = config.with_seed(seed);
+    }
+    if cli.lattice_limit.is_none()
+        && let Some(cells) = meta.lattice_limit()
+    {
+        config = config.with_lattice_limit(cells);
+    }
+    if cli.max_steps.is_none()
+        && let Some(steps) = meta.max_steps()
+    {
+        config = config.with_max_steps(steps);
+    }
+    if cli.timeout.is_none()
+        && let Some(ms) = meta.timeout()
+    {
+        config = config.with_timeout(Duration::from_millis(ms));
+    }
+    if cli.max_output.is_none()
+        && let Some(bytes) = meta.max_output()
+    {
+        config = config.with_output_limit(bytes);
+    }
+    config
+}
+
+fn load_velo_code(path: &str) -> io::Result<String> {
+    let path = Path::new(path);
+    let code = read_source(path)?;
+    expand_includes(&code, path).map_err(io::Error::other)
+}
+
+/// The comment of each row of the program at `path`; none for a binary grid.
+fn load_comments(path: &str, options: &ParseOptions) -> Vec<Option<String>> {
+    if path.ends_with(BINARY_EXTENSION) {
+        return Vec::new();
+    }
+    load_velo_code(path)
+        .map(|code| {
+            options
+                .comments(&code)
+                .into_iter()
+                .map(|comment| comment.map(String::from))
+                .collect()
         })
-        .collect();
-
-    let height = runes.len();
-    let width = runes.iter().map(|line| line.len()).max().unwrap_or(0);
-
-    Cosmos::new(runes, width, height)
-}
-
-fn char_to_rune(c: char) -> Rune {
-    match c {
-        '^' => Rune::ThrustUp,
-        'v' => Rune::ThrustDown,
-        '<' => Rune::ThrustLeft,
-        '>' => Rune::ThrustRight,
-        'P' => Rune::Parking,
-        '+' => Rune::EntropyIncrease,
-        '-' => Rune::EntropyDecrease,
-        '[' => Rune::SteerLeft,
-        ']' => Rune::SteerRight,
-        ',' => Rune::Input,
-        '.' => Rune::Output,
-        'D' => Rune::Debug,
-        _ => Rune::Void,
+        .unwrap_or_default()
+}
+
+/// Whether the text source at `path` inlines other files, which formatting
+/// in place would flatten into it.
+fn has_includes(path: &str) -> bool {
+    read_source(Path::new(path))
+        .is_ok_and(|code| code.lines().any(|line| include_target(line).is_some()))
+}
+
+fn check_velo_code(cosmos: Cosmos) -> ExitCode {
+    match cosmos.validated() {
+        Ok(_) => ExitCode::SUCCESS,
+        Err(error) => {
+            eprintln!("{:}", error);
+            ExitCode::from(SETUP_FAILURE)
+        }
+    }
+}
+
+fn write_velo_binary(cosmos: &Cosmos, path: &str) -> ExitCode {
+    let written = File::create(path).and_then(|file| {
+        let mut writer = BufWriter::new(file);
+        cosmos.write_binary(&mut writer)?;
+        writer.flush()
+    });
+
+    match written {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(msg) => {
+            eprintln!("Failed to write binary grid. {:}", msg);
+            ExitCode::from(SETUP_FAILURE)
+        }
+    }
+}
+
+/// Prints the canonical form of a program: its metadata `header`, then the
+/// grid, each row followed by its comment from `comments`, if any.
+fn format_velo_code(
+    cosmos: &Cosmos,
+    header: &str,
+    comments: &[Option<String>],
+    path: &str,
+    in_place: bool,
+) -> ExitCode {
+    let mut formatted = header.to_string();
+    for (y, row) in cosmos.to_string().lines().enumerate() {
+        formatted.push_str(row);
+        // Rows are padded to the grid width, so the comments line up. A space
+        // before them would widen the grid the next time it is parsed.
+        if let Some(Some(comment)) = comments.get(y) {
+            formatted.push_str(comment);
+        }
+        formatted.push('\n');
+    }
+
+    if !in_place {
+        print!("{}", formatted);
+        return ExitCode::SUCCESS;
+    }
+
+    match fs::write(path, formatted) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(msg) => {
+            eprintln!("Failed to write velo file. {:}", msg);
+            ExitCode::from(SETUP_FAILURE)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A file in the temp directory, removed when dropped.
+    struct TempFile(std::path::PathBuf);
+
+    impl TempFile {
+        fn new(name: &str, contents: &[u8]) -> Self {
+            let path = std::env::temp_dir().join(format!("velo-{}-{}", std::process::id(), name));
+            fs::write(&path, contents).unwrap();
+            Self(path)
+        }
+
+        fn path(&self) -> &str {
+            self.0.to_str().unwrap()
+        }
+    }
+
+    impl Drop for TempFile {
+        fn drop(&mut self) {
+            let _ = fs::remove_file(&self.0);
+        }
+    }
+
+    #[test]
+    fn transpose_cannot_be_combined_with_fmt() {
+        assert!(Args::try_parse_from(["velo", "--transpose", "prog.velo"]).is_ok());
+        let both = Args::try_parse_from(["velo", "--transpose", "--fmt", "prog.velo"]);
+        assert!(both.is_err());
+    }
+
+    #[test]
+    fn lattice_flag_takes_comma_separated_cells() {
+        let cli = Args::try_parse_from(["velo", "--lattice", "0,72,105", "hi.velo"]);
+        assert_eq!(cli.ok().and_then(|cli| cli.lattice), Some(vec![0, 72, 105]));
+        assert!(Args::try_parse_from(["velo", "--lattice", "0,-1", "hi.velo"]).is_err());
+    }
+
+    #[test]
+    fn runes_needs_no_program_file() {
+        assert!(Args::try_parse_from(["velo", "--runes"]).is_ok());
+        assert!(Args::try_parse_from(["velo"]).is_err());
+    }
+
+    #[test]
+    fn the_animation_window_follows_the_vessel_within_the_grid() {
+        // Grids smaller than the window are drawn whole.
+        assert_eq!(window_start(5, 10, 20), 0);
+        // Otherwise the vessel stays centred until the window reaches an edge.
+        assert_eq!(window_start(3, 100, 20), 0);
+        assert_eq!(window_start(50, 100, 20), 40);
+        assert_eq!(window_start(99, 100, 20), 80);
+    }
+
+    #[test]
+    fn programs_read_the_input_file_instead_of_stdin() {
+        let file = TempFile::new("input.txt", b"*");
+        let input = BufReader::new(File::open(file.path()).unwrap());
+        let cosmos = Cosmos::parse(">,Q").unwrap();
+        let report = run_velo_code(
+            cosmos,
+            Config::default(),
+            Some(input),
+            Vec::new(),
+            None,
+            None,
+            None,
+        );
+        assert_eq!(report.unwrap().termination(), Termination::Exited(b'*'));
+    }
+
+    #[test]
+    fn check_fails_for_programs_that_cannot_halt() {
+        let check = |source: &str| check_velo_code(Cosmos::parse(source).unwrap());
+        assert_eq!(check(">+<"), ExitCode::SUCCESS);
+        assert_eq!(check(">+."), ExitCode::from(SETUP_FAILURE));
+    }
+
+    fn binary_grid(source: &str, name: &str) -> TempFile {
+        let mut bytes = Vec::new();
+        Cosmos::parse(source)
+            .unwrap()
+            .write_binary(&mut bytes)
+            .unwrap();
+        TempFile::new(name, &bytes)
+    }
+
+    #[test]
+    fn binary_grids_obey_the_grid_cell_limit() {
+        let file = binary_grid(">+.\n v<", "limit.velob");
+
+        let options = ParseOptions::default().with_max_grid_cells(5);
+        let result = load_velo_program(file.path(), &options).unwrap();
+        assert!(matches!(
+            result,
+            Err(ParseError::TooLarge { cells: 6, limit: 5 })
+        ));
+
+        let options = ParseOptions::default().with_max_grid_cells(6);
+        let (cosmos, _) = load_velo_program(file.path(), &options).unwrap().unwrap();
+        assert_eq!(cosmos.width() * cosmos.height(), 6);
+    }
+
+    #[test]
+    fn written_binary_grids_load_back_unchanged() {
+        let file = TempFile::new("written.velob", b"");
+        let cosmos = Cosmos::parse(">+.\n v<").unwrap();
+        assert_eq!(write_velo_binary(&cosmos, file.path()), ExitCode::SUCCESS);
+
+        let options = ParseOptions::default();
+        let (loaded, _) = load_velo_program(file.path(), &options).unwrap().unwrap();
+        assert_eq!(loaded.to_string(), cosmos.to_string());
+    }
+
+    #[test]
+    fn svg_paths_stop_growing_at_the_point_limit() {
+        let file = TempFile::new("path.svg", b"");
+        let svg = BufWriter::new(File::create(file.path()).unwrap());
+        let cosmos = Cosmos::parse(">  v\n^  <").unwrap();
+        let config = Config::default().with_max_steps(2 * MAX_PATH_POINTS as u64);
+        let mut machine = Machine::new_from(&cosmos, config).unwrap();
+        let report = draw_path(&mut machine, svg);
+        assert_eq!(report.termination(), Termination::StepLimit);
+
+        let drawn = fs::read_to_string(file.path()).unwrap();
+        let points = drawn.split("points=\"").nth(1).unwrap();
+        let points = &points[..points.find('"').unwrap()];
+        assert_eq!(points.split(' ').count(), MAX_PATH_POINTS);
+    }
+
+    #[test]
+    fn flags_take_precedence_over_the_metadata_header() {
+        let source = "# velo: max-steps=1000\n>  v\n^  <";
+        let (cosmos, meta) = Cosmos::parse_with_meta(source, &ParseOptions::default()).unwrap();
+        let steps_with = |flags: &[&str]| {
+            let cli = Args::try_parse_from(["velo"].iter().chain(flags)).unwrap();
+            let mut config = Config::default();
+            if let Some(steps) = cli.max_steps {
+                config = config.with_max_steps(steps);
+            }
+            let config = with_program_meta(config, &cli, &meta);
+            let report = Machine::new_from(&cosmos, config).unwrap().run();
+            assert_eq!(report.termination(), Termination::StepLimit);
+            report.steps()
+        };
+        assert_eq!(steps_with(&["prog.velo"]), 1000);
+        assert_eq!(steps_with(&["--max-steps", "5", "prog.velo"]), 5);
+    }
+
+    #[test]
+    fn formatting_keeps_the_header_and_row_comments() {
+        let source = "# velo: seed=3\n>+.   # print it\n v\n\t<# back\n";
+        let file = TempFile::new("comments.velo", source.as_bytes());
+        let options = ParseOptions::default();
+        let format_in_place = || {
+            let (cosmos, meta) = load_velo_program(file.path(), &options).unwrap().unwrap();
+            let header = meta.header(options.comment_chars()[0]);
+            let comments = load_comments(file.path(), &options);
+            let code = format_velo_code(&cosmos, &header, &comments, file.path(), true);
+            assert_eq!(code, ExitCode::SUCCESS);
+            fs::read_to_string(file.path()).unwrap()
+        };
+
+        let formatted = format_in_place();
+        assert_eq!(
+            formatted,
+            "# velo: seed=3\n>+.   # print it\n v    \n <    # back\n"
+        );
+        assert_eq!(format_in_place(), formatted);
     }
 }
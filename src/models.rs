@@ -1,31 +1,527 @@
-use std::io::{self, Read};
+use std::fmt;
+use std::io::{self, BufRead, Read, Write};
+
+use crate::context::ExecutionContext;
+use crate::sail::OutputEncoding;
 
 /// The fundamental elements in the Velo cosmos that affect the Vessel's movement.
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum Rune {
-    ThrustUp,        // '^' - Changes direction/speed, changes Resonance Frequency (Pointer)
-    ThrustDown,      // 'v' - Changes direction/speed, changes Resonance Frequency (Pointer)
-    ThrustLeft,      // '<' - Changes direction/speed, affecting Resonance Frequency
-    ThrustRight,     // '>' - Changes direction/speed, affecting Resonance Frequency
-    Parking,         // 'P' - Resets velocity to 1
-    EntropyIncrease, // '+' - Increases current data cell's entropy level by 1
-    EntropyDecrease, // '-' - Decreases current data cell's entropy level by 1
-    SteerLeft,       // '[' - Conditional 90-degree left turn
-    SteerRight,      // ']' - Conditional 90-degree right turn
-    Input,           // ',' - Reads a byte from input to the current data cell
-    Output,          // '.' - Prints the current data cell's value as an ASCII character
+    ThrustUp,           // '^' - Changes direction/speed, changes Resonance Frequency (Pointer)
+    ThrustDown,         // 'v' - Changes direction/speed, changes Resonance Frequency (Pointer)
+    ThrustLeft,         // '<' - Changes direction/speed, affecting Resonance Frequency
+    ThrustRight,        // '>' - Changes direction/speed, affecting Resonance Frequency
+    ThrustRandom,       // '?' - Acts as one of the four Thrust runes, chosen at random
+    Parking,            // 'P' - Resets velocity to 1
+    EntropyIncrease,    // '+' - Increases current data cell's entropy level by 1
+    EntropyDecrease,    // '-' - Decreases current data cell's entropy level by 1
+    SteerLeft,          // '[' - Conditional 90-degree left turn
+    SteerRight,         // ']' - Conditional 90-degree right turn
+    Input,              // ',' - Reads a byte from input to the current data cell
+    Output,             // '.' - Prints the current data cell's value as an ASCII character
+    InputNumber,        // '&' - Reads a decimal, hex or binary number into the current data cell
+    StoreVelocity,      // 'V' - Stores the current velocity (Pointer) in the current data cell
+    ThrustIfNonzero,    // 'T' - Increases velocity by 1 if the current data cell is not 0
+    SkipIfLess,         // 'L' - Jumps over the next Rune if the cell is less than the next cell
+    ReverseIfZero,      // 'U' - Conditional 180-degree turn when the current data cell is 0
+    OutputVelocity,     // '!' - Prints the velocity (Pointer) as a decimal number
+    InputAvailable,     // 'E' - Sets the current data cell to 1 if input remains, 0 at EOF
+    InputAndSkipIfZero, // '~' - Reads a byte; jumps over the next Rune if it is 0
+    InputLength,        // 'C' - Consumes an input line, storing its length
+    AdvanceByCell,      // '}' - Increases velocity by the current cell value
+    RetreatByCell,      // '{' - Decreases velocity by the current cell value
+    Push,               // '(' - Pushes the current cell onto the stack
+    Pop,                // ')' - Pops the stack into the current cell
+    Add,                // 'A' - Pops two values and pushes their sum
+    Exit,               // 'Q' - Halts with the current cell as the exit code
+    Mod,                // '%' - Sets the cell to itself modulo the next cell
+    SteerByAhead,       // 'W' - Turns right if the Rune ahead is Void
+    Spawn,              // '*' - Spawns a copy of the Vessel heading right
+    Rotate,             // 'R' - Rotates the Cosmos 90 degrees clockwise
+    Newline,            // 'N' - Prints a newline
+    SteerByValue,       // 'J' - Turns by the current cell modulo 4
+    AddNext,            // 'S' - Adds the next cell to the current cell
+    SaveDir,            // 'K' - Saves the heading in the direction register
+    RestoreDir,         // 'k' - Turns back to the saved heading
+    SubNext,            // 'M' - Subtracts the next cell from the current cell
+    PeekAt,             // '@' - Loads the cell indexed by the current cell
+    OutputIfNonzero,    // ':' - Prints the current cell unless it is 0
+    ClearAll,           // 'X' - Zeroes every data cell
+    OutputCycle,        // '$' - Prints the cycle number
+    HaltIfFast,         // 'F' - Halts if velocity exceeds the current cell
+    LoadWidth,          // 'w' - Stores the cosmos width in the current cell
+    LoadHeight,         // 'h' - Stores the cosmos height in the current cell
+    Not,                // 'I' - Inverts every bit of the current cell
+    ShiftLeft,          // 'l' - Shifts the current cell one bit left
+    ShiftRight,         // 'r' - Shifts the current cell one bit right
+    ParkIfZero,         // 'p' - Resets velocity to 1 if the current cell is 0
+    OutputString,       // '"' - Prints the string table entry indexed by the current cell
+    SlurpInput,         // 'B' - Reads all remaining input into the cells after the current one
+    DupForward,         // '=' - Copies the current cell into the next one
+    StopIfZero,         // 'Z' - Halts if the current cell is 0
+    RandomByte,         // 'b' - Stores a random value from 0 to 255 in the current cell
+    FlushInput,         // '_' - Discards input up to and including the next newline
+    OutputHex,          // 'x' - Prints the current cell as lowercase hexadecimal
+    CopyToRegister,     // 'c' - Copies the current cell into the value register
+    PasteFromRegister,  // 'g' - Stores the value register in the current cell
+    OutputIfEqual,      // 'e' - Prints the current cell if it equals the value register
+    SetLabel,           // 'm' - Records this cell under the label in the current cell
+    GotoLabel,          // 'G' - Jumps to the cell recorded under the label in the current cell
+    StoreDistance,      // 'd' - Stores the number of cells the Vessel has moved in the current cell
+    OutputRepeated,     // 'o' - Prints the current cell as many times as the next cell says
     Debug,
     Void, // Other characters - No effect
 }
 
 impl Rune {
+    /// Every Rune except `Void`, in documentation order.
+    pub const ALL: [Rune; 63] = [
+        Self::ThrustUp,
+        Self::ThrustDown,
+        Self::ThrustLeft,
+        Self::ThrustRight,
+        Self::ThrustRandom,
+        Self::Parking,
+        Self::EntropyIncrease,
+        Self::EntropyDecrease,
+        Self::SteerLeft,
+        Self::SteerRight,
+        Self::Input,
+        Self::Output,
+        Self::InputNumber,
+        Self::StoreVelocity,
+        Self::ThrustIfNonzero,
+        Self::SkipIfLess,
+        Self::ReverseIfZero,
+        Self::OutputVelocity,
+        Self::InputAvailable,
+        Self::InputAndSkipIfZero,
+        Self::InputLength,
+        Self::AdvanceByCell,
+        Self::RetreatByCell,
+        Self::Push,
+        Self::Pop,
+        Self::Add,
+        Self::Exit,
+        Self::Mod,
+        Self::SteerByAhead,
+        Self::Spawn,
+        Self::Rotate,
+        Self::Newline,
+        Self::SteerByValue,
+        Self::AddNext,
+        Self::SaveDir,
+        Self::RestoreDir,
+        Self::SubNext,
+        Self::PeekAt,
+        Self::OutputIfNonzero,
+        Self::ClearAll,
+        Self::OutputCycle,
+        Self::HaltIfFast,
+        Self::LoadWidth,
+        Self::LoadHeight,
+        Self::Not,
+        Self::ShiftLeft,
+        Self::ShiftRight,
+        Self::ParkIfZero,
+        Self::OutputString,
+        Self::SlurpInput,
+        Self::DupForward,
+        Self::StopIfZero,
+        Self::RandomByte,
+        Self::FlushInput,
+        Self::OutputHex,
+        Self::CopyToRegister,
+        Self::PasteFromRegister,
+        Self::OutputIfEqual,
+        Self::SetLabel,
+        Self::GotoLabel,
+        Self::StoreDistance,
+        Self::OutputRepeated,
+        Self::Debug,
+    ];
+
+    /// Maps a source character to its Rune. Unknown characters become `Void`.
+    pub fn from_char(c: char) -> Rune {
+        match c {
+            '^' => Self::ThrustUp,
+            'v' => Self::ThrustDown,
+            '<' => Self::ThrustLeft,
+            '>' => Self::ThrustRight,
+            '?' => Self::ThrustRandom,
+            'P' => Self::Parking,
+            '+' => Self::EntropyIncrease,
+            '-' => Self::EntropyDecrease,
+            '[' => Self::SteerLeft,
+            ']' => Self::SteerRight,
+            ',' => Self::Input,
+            '.' => Self::Output,
+            '&' => Self::InputNumber,
+            'V' => Self::StoreVelocity,
+            'T' => Self::ThrustIfNonzero,
+            'L' => Self::SkipIfLess,
+            'U' => Self::ReverseIfZero,
+            '!' => Self::OutputVelocity,
+            'E' => Self::InputAvailable,
+            '~' => Self::InputAndSkipIfZero,
+            'C' => Self::InputLength,
+            '}' => Self::AdvanceByCell,
+            '{' => Self::RetreatByCell,
+            '(' => Self::Push,
+            ')' => Self::Pop,
+            'A' => Self::Add,
+            'Q' => Self::Exit,
+            '%' => Self::Mod,
+            'W' => Self::SteerByAhead,
+            '*' => Self::Spawn,
+            'R' => Self::Rotate,
+            'N' => Self::Newline,
+            'J' => Self::SteerByValue,
+            'S' => Self::AddNext,
+            'K' => Self::SaveDir,
+            'k' => Self::RestoreDir,
+            'M' => Self::SubNext,
+            '@' => Self::PeekAt,
+            ':' => Self::OutputIfNonzero,
+            'X' => Self::ClearAll,
+            '$' => Self::OutputCycle,
+            'F' => Self::HaltIfFast,
+            'w' => Self::LoadWidth,
+            'h' => Self::LoadHeight,
+            'I' => Self::Not,
+            'l' => Self::ShiftLeft,
+            'r' => Self::ShiftRight,
+            'p' => Self::ParkIfZero,
+            '"' => Self::OutputString,
+            'B' => Self::SlurpInput,
+            '=' => Self::DupForward,
+            'Z' => Self::StopIfZero,
+            'b' => Self::RandomByte,
+            '_' => Self::FlushInput,
+            'x' => Self::OutputHex,
+            'c' => Self::CopyToRegister,
+            'g' => Self::PasteFromRegister,
+            'e' => Self::OutputIfEqual,
+            'm' => Self::SetLabel,
+            'G' => Self::GotoLabel,
+            'd' => Self::StoreDistance,
+            'o' => Self::OutputRepeated,
+            'D' => Self::Debug,
+            _ => Self::Void,
+        }
+    }
+
+    /// Maps a Rune back to its canonical source character. `Void` becomes a space.
+    pub fn to_char(self) -> char {
+        match self {
+            Self::ThrustUp => '^',
+            Self::ThrustDown => 'v',
+            Self::ThrustLeft => '<',
+            Self::ThrustRight => '>',
+            Self::ThrustRandom => '?',
+            Self::Parking => 'P',
+            Self::EntropyIncrease => '+',
+            Self::EntropyDecrease => '-',
+            Self::SteerLeft => '[',
+            Self::SteerRight => ']',
+            Self::Input => ',',
+            Self::Output => '.',
+            Self::InputNumber => '&',
+            Self::StoreVelocity => 'V',
+            Self::ThrustIfNonzero => 'T',
+            Self::SkipIfLess => 'L',
+            Self::ReverseIfZero => 'U',
+            Self::OutputVelocity => '!',
+            Self::InputAvailable => 'E',
+            Self::InputAndSkipIfZero => '~',
+            Self::InputLength => 'C',
+            Self::AdvanceByCell => '}',
+            Self::RetreatByCell => '{',
+            Self::Push => '(',
+            Self::Pop => ')',
+            Self::Add => 'A',
+            Self::Exit => 'Q',
+            Self::Mod => '%',
+            Self::SteerByAhead => 'W',
+            Self::Spawn => '*',
+            Self::Rotate => 'R',
+            Self::Newline => 'N',
+            Self::SteerByValue => 'J',
+            Self::AddNext => 'S',
+            Self::SaveDir => 'K',
+            Self::RestoreDir => 'k',
+            Self::SubNext => 'M',
+            Self::PeekAt => '@',
+            Self::OutputIfNonzero => ':',
+            Self::ClearAll => 'X',
+            Self::OutputCycle => '$',
+            Self::HaltIfFast => 'F',
+            Self::LoadWidth => 'w',
+            Self::LoadHeight => 'h',
+            Self::Not => 'I',
+            Self::ShiftLeft => 'l',
+            Self::ShiftRight => 'r',
+            Self::ParkIfZero => 'p',
+            Self::OutputString => '"',
+            Self::SlurpInput => 'B',
+            Self::DupForward => '=',
+            Self::StopIfZero => 'Z',
+            Self::RandomByte => 'b',
+            Self::FlushInput => '_',
+            Self::OutputHex => 'x',
+            Self::CopyToRegister => 'c',
+            Self::PasteFromRegister => 'g',
+            Self::OutputIfEqual => 'e',
+            Self::SetLabel => 'm',
+            Self::GotoLabel => 'G',
+            Self::StoreDistance => 'd',
+            Self::OutputRepeated => 'o',
+            Self::Debug => 'D',
+            Self::Void => ' ',
+        }
+    }
+
+    /// A human readable name, as used in the Rune Set documentation.
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::ThrustUp => "Thrust Up",
+            Self::ThrustDown => "Thrust Down",
+            Self::ThrustLeft => "Thrust Left",
+            Self::ThrustRight => "Thrust Right",
+            Self::ThrustRandom => "Random Thrust",
+            Self::Parking => "Parking",
+            Self::EntropyIncrease => "Entropy Charge",
+            Self::EntropyDecrease => "Entropy Drain",
+            Self::SteerLeft => "Steer Left",
+            Self::SteerRight => "Steer Right",
+            Self::Input => "Input",
+            Self::Output => "Output",
+            Self::InputNumber => "Number Input",
+            Self::StoreVelocity => "Store Velocity",
+            Self::ThrustIfNonzero => "Conditional Thrust",
+            Self::SkipIfLess => "Skip If Less",
+            Self::ReverseIfZero => "Reverse If Zero",
+            Self::OutputVelocity => "Output Velocity",
+            Self::InputAvailable => "Input Available",
+            Self::InputAndSkipIfZero => "Input Or Skip",
+            Self::InputLength => "Input Length",
+            Self::AdvanceByCell => "Advance By Cell",
+            Self::RetreatByCell => "Retreat By Cell",
+            Self::Push => "Push",
+            Self::Pop => "Pop",
+            Self::Add => "Add",
+            Self::Exit => "Exit",
+            Self::Mod => "Modulo",
+            Self::SteerByAhead => "Steer By Ahead",
+            Self::Spawn => "Spawn",
+            Self::Rotate => "Rotate Cosmos",
+            Self::Newline => "Newline",
+            Self::SteerByValue => "Steer By Value",
+            Self::AddNext => "Add Next",
+            Self::SaveDir => "Save Direction",
+            Self::RestoreDir => "Restore Direction",
+            Self::SubNext => "Subtract Next",
+            Self::PeekAt => "Peek At",
+            Self::OutputIfNonzero => "Output If Nonzero",
+            Self::ClearAll => "Clear All",
+            Self::OutputCycle => "Output Cycle",
+            Self::HaltIfFast => "Halt If Fast",
+            Self::LoadWidth => "Load Width",
+            Self::LoadHeight => "Load Height",
+            Self::Not => "Not",
+            Self::ShiftLeft => "Shift Left",
+            Self::ShiftRight => "Shift Right",
+            Self::ParkIfZero => "Park If Zero",
+            Self::OutputString => "Output String",
+            Self::SlurpInput => "Slurp Input",
+            Self::DupForward => "Dup Forward",
+            Self::StopIfZero => "Stop If Zero",
+            Self::RandomByte => "Random Byte",
+            Self::FlushInput => "Flush Input",
+            Self::OutputHex => "Output Hex",
+            Self::CopyToRegister => "Copy",
+            Self::PasteFromRegister => "Paste",
+            Self::OutputIfEqual => "Output If Equal",
+            Self::SetLabel => "Set Label",
+            Self::GotoLabel => "Goto Label",
+            Self::StoreDistance => "Store Distance",
+            Self::OutputRepeated => "Output Repeated",
+            Self::Debug => "Debug",
+            Self::Void => "Void",
+        }
+    }
+
+    /// A one-line explanation of what the Rune does, for the rune legend.
+    pub fn description(self) -> &'static str {
+        match self {
+            Self::ThrustUp => "Thrust up: +1 velocity moving up, -1 moving down, otherwise turn up",
+            Self::ThrustDown => {
+                "Thrust down: +1 velocity moving down, -1 moving up, otherwise turn down"
+            }
+            Self::ThrustLeft => {
+                "Thrust left: +1 velocity moving left, -1 moving right, otherwise turn left"
+            }
+            Self::ThrustRight => {
+                "Thrust right: +1 velocity moving right, -1 moving left, otherwise turn right"
+            }
+            Self::ThrustRandom => "Acts as one of the four Thrust runes, chosen at random",
+            Self::Parking => "Resets velocity (pointer) to 1",
+            Self::EntropyIncrease => "Increases the current cell by 1",
+            Self::EntropyDecrease => "Decreases the current cell by 1 (only if >= 1)",
+            Self::SteerLeft => "Turns 90 degrees left if the current cell is not 0",
+            Self::SteerRight => "Turns 90 degrees right if the current cell is not 0",
+            Self::Input => "Reads a byte from input into the current cell (0 at EOF)",
+            Self::Output => "Prints the current cell as a character",
+            Self::InputNumber => {
+                "Reads a decimal, 0x hex or 0b binary number into the current cell"
+            }
+            Self::StoreVelocity => "Stores the velocity (pointer) in the current cell",
+            Self::ThrustIfNonzero => "Increases velocity by 1 if the current cell is not 0",
+            Self::SkipIfLess => {
+                "Jumps over the next rune if the current cell is less than the next cell"
+            }
+            Self::ReverseIfZero => "Turns 180 degrees if the current cell is 0",
+            Self::OutputVelocity => "Prints the velocity (pointer) as a decimal number",
+            Self::InputAvailable => {
+                "Sets the current cell to 1 if input remains, 0 at EOF (consumes nothing)"
+            }
+            Self::InputAndSkipIfZero => {
+                "Reads a byte into the current cell; jumps over the next rune if it is 0"
+            }
+            Self::InputLength => {
+                "Consumes an input line and stores its length (without the newline)"
+            }
+            Self::AdvanceByCell => {
+                "Increases velocity (pointer) by the current cell value, up to MAX_VELOCITY"
+            }
+            Self::RetreatByCell => {
+                "Decreases velocity (pointer) by the current cell value, stopping at 0"
+            }
+            Self::Push => "Pushes the current cell onto the stack",
+            Self::Pop => "Pops the stack into the current cell (0 if empty)",
+            Self::Add => "Pops two values off the stack and pushes their sum",
+            Self::Exit => "Halts, using the current cell (0-63) as the exit code",
+            Self::Mod => {
+                "Sets the current cell to itself modulo the next cell (unchanged if that is 0)"
+            }
+            Self::SteerByAhead => {
+                "Turns right if the rune ahead is Void or the edge, else goes straight"
+            }
+            Self::Spawn => {
+                "Spawns a copy of the vessel heading 90 degrees right, with its own lattice"
+            }
+            Self::Rotate => {
+                "Rotates the cosmos 90 degrees clockwise; the vessel keeps its cell and heading"
+            }
+            Self::Newline => "Prints a newline, whatever the current cell holds",
+            Self::SteerByValue => {
+                "Turns by the current cell mod 4: 0 straight, 1 right, 2 back, 3 left"
+            }
+            Self::AddNext => "Adds the next cell (at velocity + 1) to the current cell",
+            Self::SaveDir => "Saves the current heading in the direction register",
+            Self::RestoreDir => "Turns to the heading saved by K (no effect if none was saved)",
+            Self::SubNext => "Subtracts the next cell from the current cell, stopping at 0",
+            Self::PeekAt => "Replaces the current cell with the cell at the index it holds",
+            Self::OutputIfNonzero => "Prints the current cell as a character unless it is 0",
+            Self::ClearAll => "Sets every data cell to 0, keeping the lattice size",
+            Self::OutputCycle => "Prints the current cycle number as a decimal",
+            Self::HaltIfFast => "Halts if the velocity is greater than the current cell",
+            Self::LoadWidth => "Stores the width of the cosmos in the current cell",
+            Self::LoadHeight => "Stores the height of the cosmos in the current cell",
+            Self::Not => "Replaces the current cell with its bitwise complement",
+            Self::ShiftLeft => "Shifts the current cell one bit left, dropping the top bit",
+            Self::ShiftRight => "Shifts the current cell one bit right, dropping the lowest bit",
+            Self::ParkIfZero => "Resets velocity (pointer) to 1 if the current cell is 0",
+            Self::OutputString => "Prints the string table entry indexed by the current cell",
+            Self::SlurpInput => {
+                "Reads all remaining input into the following cells, storing the count in the current cell"
+            }
+            Self::DupForward => "Copies the current cell into the next cell",
+            Self::StopIfZero => "Halts if the current cell is 0",
+            Self::RandomByte => "Stores a random value from 0 to 255 in the current cell",
+            Self::FlushInput => "Discards input up to and including the next newline",
+            Self::OutputHex => {
+                "Prints the current cell as a lowercase hexadecimal number of at least two digits"
+            }
+            Self::CopyToRegister => "Copies the current cell into the value register",
+            Self::PasteFromRegister => {
+                "Stores the value copied by c in the current cell (0 if none was copied)"
+            }
+            Self::OutputIfEqual => {
+                "Prints the current cell as a character if it equals the value copied by c"
+            }
+            Self::SetLabel => "Records this cell's coordinate under the label in the current cell",
+            Self::GotoLabel => {
+                "Moves the vessel to the cell recorded by m under the label in the current cell"
+            }
+            Self::StoreDistance => {
+                "Stores the number of cells the vessel has moved so far in the current cell"
+            }
+            Self::OutputRepeated => {
+                "Prints the current cell as a character as many times as the next cell's value"
+            }
+            Self::Debug => "Prints the vessel state in debug or trace mode",
+            Self::Void => "No effect",
+        }
+    }
+
+    /// The Rune with the same meaning in a Cosmos reflected across its main diagonal.
+    ///
+    /// Reflection swaps the horizontal and vertical axes and flips the
+    /// handedness of turns.
+    fn transposed(self) -> Rune {
+        match self {
+            Self::ThrustUp => Self::ThrustLeft,
+            Self::ThrustLeft => Self::ThrustUp,
+            Self::ThrustDown => Self::ThrustRight,
+            Self::ThrustRight => Self::ThrustDown,
+            Self::SteerLeft => Self::SteerRight,
+            Self::SteerRight => Self::SteerLeft,
+            // The transposed grid's width is the original height.
+            Self::LoadWidth => Self::LoadHeight,
+            Self::LoadHeight => Self::LoadWidth,
+            // See `turns_in_transpose`.
+            other => other,
+        }
+    }
+
+    /// Whether the Rune turns the Vessel or the grid with a fixed handedness
+    /// that `transposed` cannot mirror, as it has no twin turning the other
+    /// way. A transposed program using it behaves differently.
+    pub fn turns_in_transpose(self) -> bool {
+        matches!(
+            self,
+            Self::SteerByAhead | Self::SteerByValue | Self::Spawn | Self::Rotate
+        )
+    }
+
+    /// The Rune as it appears after the grid is turned 90 degrees clockwise.
+    fn rotated_clockwise(self) -> Rune {
+        match self {
+            Self::ThrustUp => Self::ThrustRight,
+            Self::ThrustRight => Self::ThrustDown,
+            Self::ThrustDown => Self::ThrustLeft,
+            Self::ThrustLeft => Self::ThrustUp,
+            other => other,
+        }
+    }
+
     /// Executes the action associated with this Rune on the Vessel.
-    fn act_on(&self, vessel: &mut Vessel) {
+    fn act_on(
+        &self,
+        vessel: &mut Vessel,
+        cosmos: &Cosmos,
+        context: &mut ExecutionContext,
+    ) -> Impact {
         match self {
             Self::ThrustUp => vessel.apply_directional_thrust(Direction::Up),
             Self::ThrustDown => vessel.apply_directional_thrust(Direction::Down),
             Self::ThrustLeft => vessel.apply_directional_thrust(Direction::Left),
             Self::ThrustRight => vessel.apply_directional_thrust(Direction::Right),
+            Self::ThrustRandom => {
+                let direction = Direction::from_i32(context.rng().below(4) as i32);
+                vessel.apply_directional_thrust(direction);
+            }
             Self::Parking => vessel.apply_parking(),
             Self::EntropyIncrease => vessel.charge_entropy(),
             Self::EntropyDecrease => vessel.drain_entropy(),
@@ -42,34 +538,315 @@ impl Rune {
                 }
             }
             Self::Input => {
-                // Reads the first available byte from stdin into the current data cell.
-                let mut buffer = [0; 1];
-
-                match io::stdin().read_exact(&mut buffer) {
-                    Ok(_) => {
-                        vessel.set_entropy_level(buffer[0] as u32);
+                // Reads the first available input byte into the current data cell.
+                match context.read_byte() {
+                    Some(byte) => {
+                        vessel.set_entropy_level(byte as u32);
                     }
-                    Err(_) => {
+                    None => {
                         // On EOF or read error, set the cell value to 0.
                         vessel.set_entropy_level(0);
                     }
                 }
             }
             Self::Output => {
-                // Prints the current data cell's entropy level as an ASCII character.
-                let value = vessel.current_entropy();
-                if let Some(c) = char::from_u32(value) {
-                    print!("{}", c);
-                } else {
-                    eprintln!("Velo Warning: Cannot output valid ASCII value: {}", value);
+                // Prints the current data cell's entropy level as a character.
+                if let Some(bytes) = encode_output(vessel.current_entropy(), context) {
+                    context.write_output(&bytes);
+                }
+            }
+            Self::InputNumber => {
+                // On EOF or an invalid number, set the cell value to 0.
+                let value = read_number(context.input()).unwrap_or(0);
+                vessel.set_entropy_level(value);
+            }
+            Self::StoreVelocity => vessel.store_velocity(),
+            Self::ThrustIfNonzero => {
+                // If Entropy Level is NOT zero (i.e., NOT stable), apply forward thrust.
+                if !vessel.is_stable() {
+                    vessel.increase_velocity();
+                }
+            }
+            Self::SkipIfLess => {
+                // Compares the current data cell with its neighbour at `velocity + 1`.
+                if vessel.current_entropy() < vessel.next_entropy() {
+                    return Impact::Skip;
+                }
+            }
+            Self::ReverseIfZero => {
+                // If Entropy Level is zero (i.e., stable), bounce back the way we came.
+                if vessel.is_stable() {
+                    vessel.rotate_vessel(Rotation::UTurn);
+                }
+            }
+            Self::OutputVelocity => {
+                // Prints the data pointer itself, e.g. `3`.
+                context.write_output(vessel.velocity().to_string().as_bytes());
+            }
+            Self::InputAvailable => {
+                // Peeks at the input without consuming a byte.
+                let available = context.has_input();
+                vessel.set_entropy_level(available as u32);
+            }
+            Self::InputAndSkipIfZero => {
+                // Fuses `,` with a zero test, so EOF (stored as 0) can end a read loop.
+                let value = context.read_byte().unwrap_or(0) as u32;
+                vessel.set_entropy_level(value);
+                if value == 0 {
+                    return Impact::Skip;
+                }
+            }
+            Self::InputLength => {
+                // Consumes the rest of the line; the newline is consumed but not counted.
+                let mut length = 0;
+                while let Some(byte) = context.read_byte() {
+                    if byte == b'\n' {
+                        break;
+                    }
+                    length += 1;
+                }
+                vessel.set_entropy_level(length);
+            }
+            Self::AdvanceByCell => vessel.shift_velocity_by_cell(true),
+            Self::RetreatByCell => vessel.shift_velocity_by_cell(false),
+            Self::Push => vessel.push_current_entropy(),
+            Self::Pop => vessel.pop_into_current_entropy(),
+            Self::Add => vessel.add_stack_top(),
+            Self::Exit => {
+                // Larger values saturate, keeping clear of Velo's own statuses.
+                let code = vessel.current_entropy().min(MAX_EXIT_CODE as u32) as u8;
+                return Impact::Exit(code);
+            }
+            Self::Mod => {
+                // A zero divisor leaves the cell unchanged.
+                let divisor = vessel.next_entropy();
+                if divisor != 0 {
+                    let value = vessel.current_entropy() % divisor;
+                    vessel.set_entropy_level(value);
+                }
+            }
+            Self::SteerByAhead => {
+                // Looks at the grid, not the data: turns right when the next cell is empty.
+                // Past the edge counts as Void, so the Vessel turns instead of leaving.
+                let ahead = match vessel.get_next_coordinate() {
+                    Ok((x, y)) => cosmos.get(x, y),
+                    Err(_) => Self::Void,
+                };
+                if ahead == Self::Void {
+                    vessel.rotate_vessel(Rotation::Right);
+                }
+            }
+            Self::Spawn => return Impact::Spawn,
+            Self::Rotate => return Impact::Rotate,
+            Self::Newline => context.write_output(b"\n"),
+            Self::SteerByValue => {
+                // A computed branch: 0 straight, 1 right, 2 back, 3 left.
+                let rotation = match vessel.current_entropy() % 4 {
+                    0 => Rotation::Straight,
+                    1 => Rotation::Right,
+                    2 => Rotation::UTurn,
+                    _ => Rotation::Left,
+                };
+                vessel.rotate_vessel(rotation);
+            }
+            Self::AddNext => {
+                // The neighbour at `velocity + 1` is allocated (as 0) if needed.
+                let sum = vessel.current_entropy().wrapping_add(vessel.next_entropy());
+                vessel.set_entropy_level(sum);
+            }
+            Self::SaveDir => vessel.save_direction(),
+            Self::RestoreDir => vessel.restore_direction(),
+            Self::SubNext => {
+                // Stops at 0 like `-`; the neighbour is allocated (as 0) if needed.
+                let difference = vessel
+                    .current_entropy()
+                    .saturating_sub(vessel.next_entropy());
+                vessel.set_entropy_level(difference);
+            }
+            Self::PeekAt => {
+                let index = vessel.current_entropy() as usize;
+                let value = vessel.peek_entropy(index);
+                vessel.set_entropy_level(value);
+            }
+            Self::OutputIfNonzero => {
+                // Like `.`, but a zero cell prints nothing instead of a NUL byte.
+                if !vessel.is_stable() {
+                    return Self::Output.act_on(vessel, cosmos, context);
+                }
+            }
+            Self::ClearAll => vessel.clear_lattice(),
+            Self::OutputCycle => context.write_output(context.step().to_string().as_bytes()),
+            Self::HaltIfFast => {
+                // A guard against runaway pointer growth: the cell holds the highest allowed velocity.
+                if vessel.velocity() > vessel.current_entropy() as usize {
+                    vessel.stop();
+                }
+            }
+            Self::LoadWidth => vessel.set_entropy_level(cosmos.width() as u32),
+            Self::LoadHeight => vessel.set_entropy_level(cosmos.height() as u32),
+            Self::Not => {
+                let inverted = !vessel.current_entropy();
+                vessel.set_entropy_level(inverted);
+            }
+            Self::ShiftLeft => {
+                // The top bit falls off; the cell is 32 bits wide.
+                let shifted = vessel.current_entropy() << 1;
+                vessel.set_entropy_level(shifted);
+            }
+            Self::ShiftRight => {
+                let shifted = vessel.current_entropy() >> 1;
+                vessel.set_entropy_level(shifted);
+            }
+            Self::ParkIfZero => {
+                // At velocity 1 this changes nothing either way.
+                if vessel.is_stable() {
+                    vessel.apply_parking();
+                }
+            }
+            Self::OutputString => {
+                let index = vessel.current_entropy();
+                match context.string_entry(index as usize) {
+                    Some(entry) => {
+                        let bytes = entry.as_bytes().to_vec();
+                        context.write_output(&bytes);
+                    }
+                    None => context.warn(format_args!("No string table entry: {}", index)),
+                }
+            }
+            Self::SlurpInput => {
+                // The bytes fill the cells after the current one, which gets their count.
+                let mut bytes = Vec::new();
+                let _ = context.input().read_to_end(&mut bytes);
+                let start = vessel.pointed_cell() + 1;
+                // Last cell first, so the lattice grows at most once.
+                for (offset, &byte) in bytes.iter().enumerate().rev() {
+                    vessel.set_entropy_at(start + offset, byte as u32);
+                }
+                vessel.set_entropy_level(bytes.len() as u32);
+            }
+            Self::DupForward => vessel.dup_forward(),
+            Self::StopIfZero => {
+                if vessel.is_stable() {
+                    vessel.stop();
+                }
+            }
+            Self::RandomByte => {
+                let byte = context.rng().below(256) as u32;
+                vessel.set_entropy_level(byte);
+            }
+            Self::FlushInput => {
+                // Waits for the newline if it has not arrived yet; stops at EOF.
+                let _ = context.input().skip_until(b'\n');
+            }
+            Self::OutputHex => {
+                let hex = format!("{:02x}", vessel.current_entropy());
+                context.write_output(hex.as_bytes());
+            }
+            Self::CopyToRegister => vessel.copy_to_register(),
+            Self::PasteFromRegister => {
+                let value = vessel.register().unwrap_or(0);
+                vessel.set_entropy_level(value);
+            }
+            Self::OutputIfEqual => {
+                // An empty register compares as 0.
+                if vessel.current_entropy() == vessel.register().unwrap_or(0) {
+                    return Self::Output.act_on(vessel, cosmos, context);
+                }
+            }
+            Self::SetLabel => return Impact::SetLabel(vessel.current_entropy()),
+            Self::GotoLabel => return Impact::GotoLabel(vessel.current_entropy()),
+            Self::StoreDistance => {
+                // Saturates rather than wrapping on a very long voyage.
+                let distance = u32::try_from(vessel.distance_traveled()).unwrap_or(u32::MAX);
+                vessel.set_entropy_level(distance);
+            }
+            Self::OutputRepeated => {
+                let count = vessel.next_entropy().min(MAX_OUTPUT_REPEAT);
+                if count > 0
+                    && let Some(bytes) = encode_output(vessel.current_entropy(), context)
+                {
+                    for _ in 0..count {
+                        // A huge count must not run past the output limit within one cycle.
+                        if context.output_limit_reached() {
+                            break;
+                        }
+                        context.write_output(&bytes);
+                    }
                 }
             }
             Self::Debug | Self::Void => (),
         }
+
+        Impact::Proceed
+    }
+}
+
+/// What the Vessel does after a Rune has acted on it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Impact {
+    Proceed,        // Continue to the next Rune as usual.
+    Skip,           // Jump over the next Rune without impacting it.
+    Exit(u8),       // Halt the program with this exit code.
+    Spawn,          // Add a copy of the Vessel, turned right, to the fleet.
+    Rotate,         // Turn the Cosmos 90 degrees clockwise around the Vessel.
+    SetLabel(u32),  // Record the Rune's coordinate under this label.
+    GotoLabel(u32), // Move the Vessel to the coordinate recorded under this label.
+}
+
+/// The bytes the Output rune writes for `value`, or `None` after a warning if
+/// the output encoding cannot represent it.
+fn encode_output(value: u32, context: &mut ExecutionContext) -> Option<Vec<u8>> {
+    match context.output_encoding() {
+        OutputEncoding::Utf8 => match char::from_u32(value) {
+            Some(c) => Some(c.encode_utf8(&mut [0; 4]).as_bytes().to_vec()),
+            None => {
+                context.warn(format_args!("Cannot output valid ASCII value: {}", value));
+                None
+            }
+        },
+        OutputEncoding::Latin1 => match u8::try_from(value) {
+            Ok(byte) => Some(vec![byte]),
+            Err(_) => {
+                context.warn(format_args!("Cannot output Latin-1 value: {}", value));
+                None
+            }
+        },
+        OutputEncoding::RawLowByte => Some(vec![value as u8]),
+    }
+}
+
+/// Reads one whitespace-delimited token and parses it as a number.
+fn read_number(reader: &mut dyn BufRead) -> Option<u32> {
+    let mut token = String::new();
+
+    for byte in reader.bytes() {
+        let c = byte.ok()? as char;
+        if c.is_ascii_whitespace() {
+            if token.is_empty() {
+                // Skip leading whitespace.
+                continue;
+            }
+            break;
+        }
+        token.push(c);
+    }
+
+    parse_number(&token)
+}
+
+/// Parses `0x`-prefixed hexadecimal, `0b`-prefixed binary, or plain decimal.
+fn parse_number(token: &str) -> Option<u32> {
+    if let Some(hex) = token.strip_prefix("0x").or(token.strip_prefix("0X")) {
+        u32::from_str_radix(hex, 16).ok()
+    } else if let Some(binary) = token.strip_prefix("0b").or(token.strip_prefix("0B")) {
+        u32::from_str_radix(binary, 2).ok()
+    } else {
+        token.parse().ok()
     }
 }
 
 /// The Velo universe, represented as a grid of Runes.
+#[derive(Clone)]
 pub struct Cosmos {
     runes: Vec<Vec<Rune>>,
     width: usize,
@@ -93,17 +870,158 @@ impl Cosmos {
         self.height
     }
 
+    /// Whether the grid has no cells, as for a source of only blank lines.
+    pub fn is_empty(&self) -> bool {
+        self.width == 0 || self.height == 0
+    }
+
     pub fn get(&self, x: usize, y: usize) -> Rune {
-        if y >= self.height || x >= self.runes[y].len() {
+        // Rows may be shorter than the width, down to none at all for blank lines.
+        if y >= self.height || x >= self.runes.get(y).map_or(0, Vec::len) {
             Rune::Void
         } else {
             self.runes[y][x]
         }
     }
+
+    /// Whether any cell of the grid holds `rune`.
+    pub fn contains(&self, rune: Rune) -> bool {
+        self.runes.iter().flatten().any(|&cell| cell == rune)
+    }
+
+    /// Swaps rows and columns, so a program written along columns runs as if
+    /// written along rows (and vice versa). Directional Runes are mirrored to
+    /// keep the program's behavior, except for `W`, `J`, `*` and `R`; see
+    /// `Rune::turns_in_transpose`.
+    pub fn transpose(&self) -> Cosmos {
+        let runes = (0..self.width)
+            .map(|x| {
+                (0..self.height)
+                    .map(|y| self.get(x, y).transposed())
+                    .collect()
+            })
+            .collect();
+
+        Cosmos::new(runes, self.height, self.width)
+    }
+
+    /// Turns the grid 90 degrees clockwise: `(x, y)` moves to `(height - 1 - y, x)`.
+    /// Thrust Runes turn with it.
+    pub fn rotate_clockwise(&self) -> Cosmos {
+        let runes = (0..self.width)
+            .map(|y| {
+                (0..self.height)
+                    .map(|x| self.get(y, self.height - 1 - x).rotated_clockwise())
+                    .collect()
+            })
+            .collect();
+
+        Cosmos::new(runes, self.height, self.width)
+    }
+}
+
+/// Builds a Cosmos from rows of Runes, taking the height from the number of
+/// rows and the width from the longest row. Shorter rows are padded with Void.
+impl From<Vec<Vec<Rune>>> for Cosmos {
+    fn from(mut runes: Vec<Vec<Rune>>) -> Self {
+        let width = runes.iter().map(Vec::len).max().unwrap_or(0);
+        for row in &mut runes {
+            row.resize(width, Rune::Void);
+        }
+        let height = runes.len();
+
+        Cosmos::new(runes, width, height)
+    }
+}
+
+// Binary grid files: these magic bytes, then the grid as written by `Cosmos::write_grid`.
+const GRID_MAGIC: &[u8; 8] = b"VELOGRID";
+
+impl Cosmos {
+    /// Writes the Cosmos in a compact binary form that loads without parsing text.
+    pub fn write_binary(&self, mut writer: impl Write) -> io::Result<()> {
+        writer.write_all(GRID_MAGIC)?;
+        self.write_grid(&mut writer)
+    }
+
+    /// Reads a Cosmos written by `write_binary`.
+    pub fn read_binary(mut reader: impl Read) -> io::Result<Cosmos> {
+        let mut magic = [0; 8];
+        if reader.read_exact(&mut magic).is_err() || &magic != GRID_MAGIC {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "not a binary velo grid",
+            ));
+        }
+
+        Cosmos::read_grid(&mut reader)
+    }
+
+    /// Width and height as little-endian u64s, then each row as one glyph byte per Rune.
+    pub(crate) fn write_grid(&self, w: &mut impl Write) -> io::Result<()> {
+        w.write_all(&(self.width as u64).to_le_bytes())?;
+        w.write_all(&(self.height as u64).to_le_bytes())?;
+        for y in 0..self.height {
+            let row: Vec<u8> = (0..self.width)
+                .map(|x| self.get(x, y).to_char() as u8)
+                .collect();
+            w.write_all(&row)?;
+        }
+        Ok(())
+    }
+
+    pub(crate) fn read_grid(r: &mut impl Read) -> io::Result<Cosmos> {
+        let read_usize = |r: &mut dyn Read| -> io::Result<usize> {
+            let mut buffer = [0; 8];
+            r.read_exact(&mut buffer)?;
+            usize::try_from(u64::from_le_bytes(buffer)).map_err(|_| {
+                io::Error::new(io::ErrorKind::InvalidData, "value does not fit in usize")
+            })
+        };
+        let width = read_usize(r)?;
+        let height = read_usize(r)?;
+        let invalid = |message| io::Error::new(io::ErrorKind::InvalidData, message);
+        if width == 0 && height != 0 {
+            // Rows without cells hold no bytes, so nothing would bound the height.
+            return Err(invalid("grid has rows but no columns"));
+        }
+        let cells = width
+            .checked_mul(height)
+            .ok_or_else(|| invalid("grid size does not fit in usize"))?;
+
+        // Read through `take`, so a corrupt size cannot allocate more than the input holds.
+        let mut bytes = Vec::new();
+        r.by_ref().take(cells as u64).read_to_end(&mut bytes)?;
+        if bytes.len() != cells {
+            return Err(invalid("grid size is larger than its data"));
+        }
+        let runes = bytes
+            .chunks(width.max(1))
+            .map(|row| {
+                row.iter()
+                    .map(|&byte| Rune::from_char(byte as char))
+                    .collect()
+            })
+            .collect();
+
+        Ok(Cosmos::new(runes, width, height))
+    }
+}
+
+/// Renders the Cosmos as canonical source: one line per row, every row padded
+/// with spaces to the full width. Comments and unknown characters are not kept.
+impl fmt::Display for Cosmos {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for y in 0..self.height {
+            let row: String = (0..self.width).map(|x| self.get(x, y).to_char()).collect();
+            writeln!(f, "{}", row)?;
+        }
+        Ok(())
+    }
 }
 
 /// The direction of the Vessel's travel.
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub enum Direction {
     Up,
     Down,
@@ -113,7 +1031,18 @@ pub enum Direction {
 }
 
 impl Direction {
-    fn to_i32(self) -> i32 {
+    /// A one-letter abbreviation for compact output.
+    pub fn short(self) -> &'static str {
+        match self {
+            Self::Up => "U",
+            Self::Down => "D",
+            Self::Left => "L",
+            Self::Right => "R",
+            Self::None => "-",
+        }
+    }
+
+    pub(crate) fn to_i32(self) -> i32 {
         match self {
             Self::Up => 0,
             Self::Right => 1,
@@ -123,7 +1052,7 @@ impl Direction {
         }
     }
 
-    fn from_i32(n: i32) -> Self {
+    pub(crate) fn from_i32(n: i32) -> Self {
         match n {
             0 => Self::Up,
             1 => Self::Right,
@@ -137,17 +1066,28 @@ impl Direction {
         self == other
     }
 
-    fn opposite_to(self, other: Self) -> bool {
-        match (self, other) {
-            (Self::Up, Self::Down) => true,
-            (Self::Down, Self::Up) => true,
-            (Self::Right, Self::Left) => true,
-            (Self::Left, Self::Right) => true,
-            _ => false,
+    /// The coordinate offset `(dx, dy)` of one step in this direction; `y` grows downwards.
+    pub fn delta(self) -> (i32, i32) {
+        match self {
+            Self::Up => (0, -1),
+            Self::Down => (0, 1),
+            Self::Left => (-1, 0),
+            Self::Right => (1, 0),
+            Self::None => (0, 0),
         }
     }
 
-    fn rotate(self, rotation: Rotation) -> Self {
+    pub(crate) fn opposite_to(self, other: Self) -> bool {
+        matches!(
+            (self, other),
+            (Self::Up, Self::Down)
+                | (Self::Down, Self::Up)
+                | (Self::Right, Self::Left)
+                | (Self::Left, Self::Right)
+        )
+    }
+
+    pub(crate) fn rotate(self, rotation: Rotation) -> Self {
         Self::from_i32((self.to_i32() + rotation.to_i32()) % 4)
     }
 }
@@ -173,7 +1113,7 @@ impl Rotation {
 }
 
 /// The main execution entity, an exploration vessel moving through the Cosmos.
-#[derive(Debug, Clone)]
+#[derive(Clone)]
 pub struct Vessel {
     x: usize,
     y: usize,
@@ -183,11 +1123,78 @@ pub struct Vessel {
     velocity: usize,
     // The potentially infinite data storage (Data Lattice).
     data_lattice: Vec<u32>,
+    // When set, the lattice has this fixed size and the pointer wraps around it.
+    ring_size: Option<usize>,
+    // Number of writes that changed a data cell, to detect a lack of progress.
+    lattice_changes: u64,
+    // Scratch values for the stack Runes; popping an empty stack yields 0.
+    stack: Vec<u32>,
+    // Perpendicular thrust also resets the velocity to 1, like parking.
+    reset_velocity_on_turn: bool,
+    // The pointer addresses cell `velocity - 1`, so it starts at cell 0.
+    zero_based_pointer: bool,
+    // Number of times the lattice had to grow to reach a cell.
+    lattice_growths: u64,
+    // Heading stored by the SaveDir rune for RestoreDir.
+    saved_direction: Option<Direction>,
+    // Value stored by the Copy rune for Paste and OutputIfEqual.
+    register: Option<u32>,
+    // Cells moved through, counted by the Machine; jumps do not count.
+    distance_traveled: u64,
+    // Most data cells the lattice may grow to; unbounded if `None`.
+    lattice_limit: Option<usize>,
+    // Set once a cell past `lattice_limit` was addressed.
+    lattice_overflowed: bool,
+}
+
+// Only the observable state is shown, which keeps traces compact.
+impl fmt::Debug for Vessel {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.debug_fields(f, &self.data_lattice)
+    }
+}
+
+/// The Vessel's `Debug` output with the addressed cell marked; see `Vessel::pointer_view`.
+pub struct PointerView<'a>(&'a Vessel);
+
+impl fmt::Debug for PointerView<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let lattice = self.0.lattice_with_pointer();
+        self.0.debug_fields(f, &format_args!("{}", lattice))
+    }
 }
 
+/// The position and motion of a Vessel, without its data; see `Vessel::state_key`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct VesselState {
+    pub x: usize,
+    pub y: usize,
+    pub direction: Direction,
+    pub velocity: usize,
+}
+
+/// Number of data cells a new Vessel starts with.
+pub const DEFAULT_LATTICE_CELLS: usize = 16;
+
+/// The highest exit code `Q` exits with. Statuses from 64 up are Velo's own;
+/// see `Termination::status`.
+pub const MAX_EXIT_CODE: u8 = 63;
+
+/// The most times one `o` prints its cell; larger counts print this many,
+/// so a single cycle cannot write billions of bytes.
+pub const MAX_OUTPUT_REPEAT: u32 = 65_536;
+
+/// The highest velocity (pointer) `}` shifts to, which bounds the lattice it can allocate.
+pub const MAX_VELOCITY: usize = 1 << 20;
+
 impl Vessel {
     /// Creates a new Vessel at the starting coordinates.
     pub fn new(x: usize, y: usize, starting_rune: Rune) -> Vessel {
+        Vessel::with_capacity(x, y, starting_rune, DEFAULT_LATTICE_CELLS)
+    }
+
+    /// Like `new`, with `cells` zeroed data cells allocated up front instead of 16.
+    pub fn with_capacity(x: usize, y: usize, starting_rune: Rune, cells: usize) -> Vessel {
         // Initial direction and velocity are determined by the top left corner Rune.
         let (direction, velocity) = match starting_rune {
             Rune::ThrustUp => (Direction::Up, 1),
@@ -201,8 +1208,56 @@ impl Vessel {
             y,
             direction,
             velocity,
-            // Initializes the data lattice with 16 starting data cells.
-            data_lattice: vec![0; 16],
+            data_lattice: vec![0; cells],
+            ring_size: None,
+            lattice_changes: 0,
+            stack: Vec::new(),
+            reset_velocity_on_turn: false,
+            zero_based_pointer: false,
+            lattice_growths: 0,
+            saved_direction: None,
+            register: None,
+            distance_traveled: 0,
+            lattice_limit: None,
+            lattice_overflowed: false,
+        }
+    }
+
+    /// Creates a new Vessel whose data lattice starts with preset cells.
+    ///
+    /// `lattice[0]` is cell 0; the pointer starts at cell 1 as usual.
+    pub fn with_lattice(x: usize, y: usize, starting_rune: Rune, lattice: Vec<u32>) -> Vessel {
+        Vessel {
+            data_lattice: lattice,
+            ..Vessel::new(x, y, starting_rune)
+        }
+    }
+
+    /// Rebuilds a Vessel from previously captured state.
+    pub(crate) fn restore(
+        x: usize,
+        y: usize,
+        direction: Direction,
+        velocity: usize,
+        data_lattice: Vec<u32>,
+    ) -> Vessel {
+        Vessel {
+            x,
+            y,
+            direction,
+            velocity,
+            data_lattice,
+            ring_size: None,
+            lattice_changes: 0,
+            stack: Vec::new(),
+            reset_velocity_on_turn: false,
+            zero_based_pointer: false,
+            lattice_growths: 0,
+            saved_direction: None,
+            register: None,
+            distance_traveled: 0,
+            lattice_limit: None,
+            lattice_overflowed: false,
         }
     }
 
@@ -224,22 +1279,224 @@ impl Vessel {
         self.velocity
     }
 
+    /// A hashable key for the Vessel's position, heading and velocity, for
+    /// sets of visited states. The lattice is left out, so it stays cheap.
+    pub fn state_key(&self) -> VesselState {
+        VesselState {
+            x: self.x,
+            y: self.y,
+            direction: self.direction,
+            velocity: self.velocity,
+        }
+    }
+
+    pub fn data_lattice(&self) -> &[u32] {
+        &self.data_lattice
+    }
+
+    /// The stack used by `(`, `)` and `A`, bottom first.
+    pub fn stack(&self) -> &[u32] {
+        &self.stack
+    }
+
+    pub(crate) fn restore_stack(&mut self, stack: Vec<u32>) {
+        self.stack = stack;
+    }
+
+    pub(crate) fn restore_lattice_growths(&mut self, lattice_growths: u64) {
+        self.lattice_growths = lattice_growths;
+    }
+
+    /// The heading saved by `K`, if any.
+    pub fn saved_direction(&self) -> Option<Direction> {
+        self.saved_direction
+    }
+
+    pub(crate) fn restore_saved_direction(&mut self, direction: Option<Direction>) {
+        self.saved_direction = direction;
+    }
+
+    /// The value copied by `c`, if any.
+    pub fn register(&self) -> Option<u32> {
+        self.register
+    }
+
+    pub(crate) fn restore_register(&mut self, value: Option<u32>) {
+        self.register = value;
+    }
+
+    /// The number of cells the Vessel has moved through so far.
+    pub fn distance_traveled(&self) -> u64 {
+        self.distance_traveled
+    }
+
+    pub(crate) fn add_distance(&mut self, cells: u64) {
+        self.distance_traveled += cells;
+    }
+
+    /// How many times the data lattice grew because a cell past its end was addressed.
+    pub fn lattice_growths(&self) -> u64 {
+        self.lattice_growths
+    }
+
+    /// The number of data cells allocated so far. The lattice never shrinks,
+    /// so this is also the peak reached during the run.
+    pub fn lattice_len(&self) -> usize {
+        self.data_lattice.len()
+    }
+
+    /// Whether a cell past the lattice limit was addressed. Such cells read
+    /// as 0 and writes to them are dropped.
+    pub fn lattice_overflowed(&self) -> bool {
+        self.lattice_overflowed
+    }
+
+    pub(crate) fn lattice_changes(&self) -> u64 {
+        self.lattice_changes
+    }
+
     // --- Data Lattice Management ---
 
-    fn check_and_expand_data_lattice(&mut self) {
-        if self.velocity >= self.data_lattice.len() {
-            self.data_lattice.resize_with(self.velocity + 16, || 0);
+    /// Turns the lattice into a ring of `size` cells (at least 1) addressed modulo its size.
+    pub(crate) fn use_ring_lattice(&mut self, size: usize) {
+        let size = size.max(1);
+        self.data_lattice.resize(size, 0);
+        self.ring_size = Some(size);
+    }
+
+    /// A new Vessel on the same cell, turned 90 degrees right, with copies of
+    /// this Vessel's velocity, data lattice and stack.
+    pub(crate) fn spawn(&self) -> Vessel {
+        let mut spawned = self.clone();
+        spawned.rotate_vessel(Rotation::Right);
+        spawned
+    }
+
+    /// Caps the lattice at `cells` data cells; see `lattice_overflowed`.
+    pub(crate) fn set_lattice_limit(&mut self, cells: usize) {
+        self.lattice_limit = Some(cells);
+    }
+
+    pub(crate) fn restore_lattice_overflowed(&mut self, overflowed: bool) {
+        self.lattice_overflowed = overflowed;
+    }
+
+    pub(crate) fn set_zero_based_pointer(&mut self, zero_based: bool) {
+        self.zero_based_pointer = zero_based;
+    }
+
+    pub(crate) fn set_reset_velocity_on_turn(&mut self, reset: bool) {
+        self.reset_velocity_on_turn = reset;
+    }
+
+    fn debug_fields(&self, f: &mut fmt::Formatter<'_>, lattice: &dyn fmt::Debug) -> fmt::Result {
+        f.debug_struct("Vessel")
+            .field("x", &self.x)
+            .field("y", &self.y)
+            .field("direction", &self.direction)
+            .field("velocity", &self.velocity)
+            .field("data_lattice", lattice)
+            .finish()
+    }
+
+    /// Formats the data lattice with the cell the pointer addresses in
+    /// parentheses, e.g. `[0, 3, (5), 0]`.
+    pub fn lattice_with_pointer(&self) -> String {
+        let index = self.cell_index(self.pointed_cell());
+        let cells: Vec<String> = self
+            .data_lattice
+            .iter()
+            .enumerate()
+            .map(|(i, cell)| {
+                if i == index {
+                    format!("({})", cell)
+                } else {
+                    cell.to_string()
+                }
+            })
+            .collect();
+
+        if index < self.data_lattice.len() {
+            format!("[{}]", cells.join(", "))
+        } else {
+            // The addressed cell has not been allocated yet, so it reads as 0.
+            format!("[{}] (cell {} unallocated)", cells.join(", "), index)
+        }
+    }
+
+    /// Like the `Debug` output, with the data lattice shown by `lattice_with_pointer`.
+    pub fn pointer_view(&self) -> PointerView<'_> {
+        PointerView(self)
+    }
+
+    /// The index of the data cell a pointer value (velocity) addresses.
+    fn cell_index(&self, pointer: usize) -> usize {
+        match self.ring_size {
+            Some(size) => pointer % size,
+            None => pointer,
+        }
+    }
+
+    /// Makes sure cell `index` exists, returning false if the lattice limit forbids it.
+    fn check_and_expand_data_lattice(&mut self, index: usize) -> bool {
+        if index >= self.data_lattice.len() {
+            let mut cells = index + 16;
+            if let Some(limit) = self.lattice_limit {
+                // Refused before allocating, so a huge pointer cannot exhaust memory.
+                if index >= limit {
+                    self.lattice_overflowed = true;
+                    return false;
+                }
+                cells = cells.min(limit);
+            }
+            self.data_lattice.resize_with(cells, || 0);
+            self.lattice_growths += 1;
+        }
+        true
+    }
+
+    /// Reads the data cell addressed by `pointer`, expanding the lattice if needed.
+    pub fn entropy_at(&mut self, pointer: usize) -> u32 {
+        let index = self.cell_index(pointer);
+        if !self.check_and_expand_data_lattice(index) {
+            return 0;
+        }
+        self.data_lattice[index]
+    }
+
+    /// Writes the data cell addressed by `pointer`, expanding the lattice if needed.
+    pub fn set_entropy_at(&mut self, pointer: usize, new_entropy_level: u32) {
+        let index = self.cell_index(pointer);
+        if !self.check_and_expand_data_lattice(index) {
+            return;
+        }
+        if self.data_lattice[index] != new_entropy_level {
+            self.data_lattice[index] = new_entropy_level;
+            self.lattice_changes += 1;
+        }
+    }
+
+    /// The lattice index the pointer addresses (before wrapping around a ring
+    /// lattice): the velocity, or one less with a zero-based pointer.
+    pub fn pointed_cell(&self) -> usize {
+        if self.zero_based_pointer {
+            self.velocity.saturating_sub(1)
+        } else {
+            self.velocity
         }
     }
 
     pub fn current_entropy(&mut self) -> u32 {
-        self.check_and_expand_data_lattice();
-        self.data_lattice[self.velocity]
+        self.entropy_at(self.pointed_cell())
     }
 
     pub fn set_entropy_level(&mut self, new_entropy_level: u32) {
-        self.check_and_expand_data_lattice();
-        self.data_lattice[self.velocity] = new_entropy_level;
+        self.set_entropy_at(self.pointed_cell(), new_entropy_level);
+    }
+
+    /// The entropy level of the neighbouring cell, after the current one.
+    pub fn next_entropy(&mut self) -> u32 {
+        self.entropy_at(self.pointed_cell() + 1)
     }
 
     pub fn is_stable(&mut self) -> bool {
@@ -251,8 +1508,13 @@ impl Vessel {
     // Note: All movement methods ensure the Vessel only moves 1 unit per execution cycle.
 
     /// The Vessel impacts a Rune, modifying its state (direction and velocity).
-    pub fn impact_rune(&mut self, rune: Rune) {
-        rune.act_on(self);
+    pub fn impact_rune(
+        &mut self,
+        rune: Rune,
+        cosmos: &Cosmos,
+        context: &mut ExecutionContext,
+    ) -> Impact {
+        rune.act_on(self, cosmos, context)
     }
 
     fn increase_velocity(&mut self) {
@@ -267,7 +1529,7 @@ impl Vessel {
 
     fn charge_entropy(&mut self) {
         // Increases the entropy level of the current data cell by 1.
-        let new_entropy_level = self.current_entropy() + 1;
+        let new_entropy_level = self.current_entropy().wrapping_add(1);
         self.set_entropy_level(new_entropy_level);
     }
 
@@ -280,6 +1542,85 @@ impl Vessel {
         }
     }
 
+    fn store_velocity(&mut self) {
+        // Exposes the data pointer itself as data.
+        self.set_entropy_level(self.velocity as u32);
+    }
+
+    fn shift_velocity_by_cell(&mut self, forward: bool) {
+        // Computed addressing: the current cell holds a pointer offset.
+        let offset = self.current_entropy() as usize;
+        self.velocity = if forward {
+            // Clamped, so one huge cell cannot allocate gigabytes of lattice. A
+            // velocity thrusted past the bound is left as it is.
+            self.velocity
+                .saturating_add(offset)
+                .min(MAX_VELOCITY.max(self.velocity))
+        } else {
+            // Retreating past cell 0 stops at 0, which halts the Vessel.
+            self.velocity.saturating_sub(offset)
+        };
+    }
+
+    fn push_current_entropy(&mut self) {
+        let value = self.current_entropy();
+        self.stack.push(value);
+    }
+
+    fn pop_into_current_entropy(&mut self) {
+        // An empty stack yields 0.
+        let value = self.stack.pop().unwrap_or(0);
+        self.set_entropy_level(value);
+    }
+
+    fn add_stack_top(&mut self) {
+        // Replaces the top two values with their sum, wrapping like a u32 cell.
+        let a = self.stack.pop().unwrap_or(0);
+        let b = self.stack.pop().unwrap_or(0);
+        self.stack.push(a.wrapping_add(b));
+    }
+
+    fn save_direction(&mut self) {
+        self.saved_direction = Some(self.direction);
+    }
+
+    fn restore_direction(&mut self) {
+        // With nothing saved, the heading is unchanged.
+        if let Some(direction) = self.saved_direction {
+            self.turn_to(direction);
+        }
+    }
+
+    /// Reads the data cell addressed by `pointer` without expanding the lattice;
+    /// unallocated cells read as 0, so arbitrary indices cannot exhaust memory.
+    fn peek_entropy(&self, pointer: usize) -> u32 {
+        let index = self.cell_index(pointer);
+        self.data_lattice.get(index).copied().unwrap_or(0)
+    }
+
+    /// Zeroes every data cell. The lattice keeps its length, so no cell has to be reallocated.
+    fn clear_lattice(&mut self) {
+        if self.data_lattice.iter().any(|&cell| cell != 0) {
+            self.data_lattice.fill(0);
+            self.lattice_changes += 1;
+        }
+    }
+
+    /// Drops the velocity to 0, which halts the Vessel at the end of the cycle.
+    fn stop(&mut self) {
+        self.velocity = 0;
+    }
+
+    /// Copies the current cell into the cell after it, leaving the current cell as it is.
+    fn dup_forward(&mut self) {
+        let entropy_level = self.current_entropy();
+        self.set_entropy_at(self.pointed_cell() + 1, entropy_level);
+    }
+
+    fn copy_to_register(&mut self) {
+        self.register = Some(self.current_entropy());
+    }
+
     fn apply_parking(&mut self) {
         // Resets the velocity/pointer to the base value.
         self.velocity = 1;
@@ -289,7 +1630,7 @@ impl Vessel {
         self.direction = new_direction;
     }
 
-    fn rotate_vessel(&mut self, rotation: Rotation) {
+    pub(crate) fn rotate_vessel(&mut self, rotation: Rotation) {
         self.direction = self.direction.rotate(rotation);
     }
 
@@ -302,35 +1643,38 @@ impl Vessel {
             // Opposite direction: Decrease velocity/pointer.
             self.decrease_velocity();
         } else {
-            // Perpendicular direction: Turn, velocity/pointer is unchanged.
+            // Perpendicular direction: Turn, velocity/pointer is unchanged unless configured.
             self.turn_to(rune_direction);
+            if self.reset_velocity_on_turn {
+                self.apply_parking();
+            }
         }
     }
 
     /// Calculates the expected next coordinate based on the current direction.
     /// Returns an error if the Vessel is moving out of bounds or has no direction.
     pub fn get_next_coordinate(&self) -> Result<(usize, usize), &'static str> {
-        match self.direction {
-            Direction::Up => {
-                if self.y < 1 {
-                    return Err(
-                        "`y` is less than 1, the vessel was going to travel out of the cosmos.",
-                    );
-                }
-                Ok((self.x, self.y - 1))
-            }
-            Direction::Down => Ok((self.x, self.y + 1)),
-            Direction::Left => {
-                if self.x < 1 {
-                    return Err(
-                        "`x` is less than 1, the vessel was going to travel out of the cosmos.",
-                    );
-                }
-                Ok((self.x - 1, self.y))
-            }
-            Direction::Right => Ok((self.x + 1, self.y)),
-            Direction::None => Err("No direction."),
+        self.get_coordinate_ahead(1)
+    }
+
+    /// Like `get_next_coordinate`, for the cell `distance` steps ahead.
+    pub fn get_coordinate_ahead(&self, distance: usize) -> Result<(usize, usize), &'static str> {
+        if self.direction == Direction::None {
+            return Err("No direction.");
         }
+
+        let (dx, dy) = self.direction.delta();
+        let distance = distance as isize;
+        let x = self
+            .x
+            .checked_add_signed(dx as isize * distance)
+            .ok_or("`x` is less than 1, the vessel was going to travel out of the cosmos.")?;
+        let y = self
+            .y
+            .checked_add_signed(dy as isize * distance)
+            .ok_or("`y` is less than 1, the vessel was going to travel out of the cosmos.")?;
+
+        Ok((x, y))
     }
 
     pub fn move_to(&mut self, new_x: usize, new_y: usize) {
@@ -338,3 +1682,153 @@ impl Vessel {
         self.y = new_y;
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_number_reads_hex_binary_and_decimal() {
+        assert_eq!(parse_number("0xFF"), Some(255));
+        assert_eq!(parse_number("0XfF"), Some(255));
+        assert_eq!(parse_number("0b1010"), Some(10));
+        assert_eq!(parse_number("42"), Some(42));
+        assert_eq!(parse_number("4294967295"), Some(u32::MAX));
+    }
+
+    #[test]
+    fn parse_number_rejects_invalid_tokens() {
+        assert_eq!(parse_number("abc"), None);
+        assert_eq!(parse_number("0x"), None);
+        assert_eq!(parse_number("0b12"), None);
+        assert_eq!(parse_number("4294967296"), None);
+        assert_eq!(parse_number(""), None);
+    }
+
+    #[test]
+    fn read_number_skips_leading_whitespace_and_stops_at_the_next() {
+        let mut input = std::io::Cursor::new(" \n 0x41 12");
+        assert_eq!(read_number(&mut input), Some(0x41));
+        assert_eq!(read_number(&mut input), Some(12));
+        assert_eq!(read_number(&mut input), None);
+    }
+
+    #[test]
+    fn display_pads_rows_and_is_idempotent() {
+        let formatted = Cosmos::parse(">+.\n v\n\t<").unwrap().to_string();
+        assert_eq!(formatted, ">+.\n v \n < \n");
+        assert_eq!(Cosmos::parse(&formatted).unwrap().to_string(), formatted);
+    }
+
+    #[test]
+    fn the_rune_legend_lists_every_rune_once() {
+        let glyphs: std::collections::HashSet<char> =
+            Rune::ALL.iter().map(|rune| rune.to_char()).collect();
+        assert_eq!(glyphs.len(), Rune::ALL.len());
+        for rune in Rune::ALL {
+            assert_eq!(Rune::from_char(rune.to_char()), rune);
+            assert!(!rune.description().is_empty(), "{:?}", rune);
+        }
+        assert!(!Rune::ALL.contains(&Rune::Void));
+    }
+
+    #[test]
+    fn opposite_directions_have_opposite_deltas() {
+        assert_eq!(Direction::Down.delta(), (0, 1));
+        assert_eq!(Direction::None.delta(), (0, 0));
+        for direction in [
+            Direction::Up,
+            Direction::Down,
+            Direction::Left,
+            Direction::Right,
+        ] {
+            let (dx, dy) = direction.delta();
+            assert_eq!(direction.rotate(Rotation::UTurn).delta(), (-dx, -dy));
+            assert_eq!(dx.abs() + dy.abs(), 1);
+        }
+    }
+
+    #[test]
+    fn rotating_turns_the_grid_and_its_thrust_runes() {
+        let cosmos = Cosmos::parse(">R\n+.").unwrap().rotate_clockwise();
+        assert_eq!(cosmos.to_string(), "+v\n.R\n");
+
+        let cosmos = Cosmos::parse(">+.").unwrap().rotate_clockwise();
+        assert_eq!((cosmos.width(), cosmos.height()), (1, 3));
+        assert_eq!(cosmos.to_string(), "v\n+\n.\n");
+    }
+
+    #[test]
+    fn transpose_mirrors_directional_runes() {
+        let cosmos = Cosmos::parse(">+v\n[]W").unwrap().transpose();
+        assert_eq!(cosmos.to_string(), "v]\n+[\n>W\n");
+        assert!(Rune::SteerByAhead.turns_in_transpose());
+        assert!(!Rune::SteerLeft.turns_in_transpose());
+    }
+
+    #[test]
+    fn the_pointer_view_marks_the_addressed_cell() {
+        let vessel = Vessel::with_lattice(0, 0, Rune::ThrustRight, vec![0, 3, 5]);
+        assert_eq!(vessel.lattice_with_pointer(), "[0, (3), 5]");
+
+        let vessel = Vessel::restore(0, 0, Direction::Right, 4, vec![0, 3]);
+        assert_eq!(vessel.lattice_with_pointer(), "[0, 3] (cell 4 unallocated)");
+    }
+
+    #[test]
+    fn cosmos_from_rows_infers_the_dimensions() {
+        let cosmos = Cosmos::from(vec![
+            vec![Rune::ThrustRight, Rune::EntropyIncrease, Rune::Output],
+            vec![Rune::ThrustUp],
+            vec![],
+        ]);
+        assert_eq!((cosmos.width(), cosmos.height()), (3, 3));
+        assert_eq!(cosmos.get(2, 1), Rune::Void);
+        assert_eq!(cosmos.to_string(), ">+.\n^  \n   \n");
+        let empty = Cosmos::from(Vec::new());
+        assert_eq!((empty.width(), empty.height()), (0, 0));
+    }
+
+    fn grid_bytes(width: u64, height: u64, cells: &[u8]) -> Vec<u8> {
+        let mut bytes = GRID_MAGIC.to_vec();
+        bytes.extend_from_slice(&width.to_le_bytes());
+        bytes.extend_from_slice(&height.to_le_bytes());
+        bytes.extend_from_slice(cells);
+        bytes
+    }
+
+    #[test]
+    fn binary_grid_round_trips() {
+        let cosmos = Cosmos::parse(">+.\n v<").unwrap();
+        let mut bytes = Vec::new();
+        cosmos.write_binary(&mut bytes).unwrap();
+        assert_eq!(bytes, grid_bytes(3, 2, b">+. v<"));
+
+        let read = Cosmos::read_binary(bytes.as_slice()).unwrap();
+        assert_eq!(read.to_string(), cosmos.to_string());
+    }
+
+    #[test]
+    fn binary_grid_without_columns_is_rejected() {
+        let bytes = grid_bytes(0, u64::MAX, b"");
+        let error = Cosmos::read_binary(bytes.as_slice()).err().unwrap();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn binary_grid_larger_than_its_data_is_rejected() {
+        let bytes = grid_bytes(2, 3, b">+.");
+        let error = Cosmos::read_binary(bytes.as_slice()).err().unwrap();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+
+        let bytes = grid_bytes(u64::MAX, 2, b">");
+        assert!(Cosmos::read_binary(bytes.as_slice()).is_err());
+    }
+
+    #[test]
+    fn empty_binary_grid_reads_as_empty() {
+        let bytes = grid_bytes(0, 0, b"");
+        let cosmos = Cosmos::read_binary(bytes.as_slice()).unwrap();
+        assert_eq!((cosmos.width(), cosmos.height()), (0, 0));
+    }
+}
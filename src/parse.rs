@@ -0,0 +1,431 @@
+use std::fmt;
+
+use crate::models::{Cosmos, Rune};
+
+/// Reasons Velo source text cannot be turned into a Cosmos.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ParseError {
+    // The grid would hold more cells than `ParseOptions::with_max_grid_cells` allows.
+    TooLarge {
+        cells: usize,
+        limit: usize,
+    },
+    // A non-ASCII character in program text under `ParseOptions::with_ascii_only`.
+    // `line` and `column` are 1-based, counted in characters.
+    NonAscii {
+        line: usize,
+        column: usize,
+        character: char,
+    },
+    // A `velo:` metadata comment that is not a list of known `key=value` pairs.
+    // `line` is 1-based.
+    InvalidMeta {
+        line: usize,
+    },
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::TooLarge { cells, limit } => write!(
+                f,
+                "The cosmos has {} cells, more than the limit of {}.",
+                cells, limit
+            ),
+            Self::NonAscii {
+                line,
+                column,
+                character,
+            } => write!(
+                f,
+                "Non-ASCII character {:?} (U+{:04X}) at line {}, column {}.",
+                character, *character as u32, line, column
+            ),
+            Self::InvalidMeta { line } => write!(
+                f,
+                "Invalid metadata at line {}: expected key=value pairs with the keys {}.",
+                line,
+                META_KEYS.join(", ")
+            ),
+        }
+    }
+}
+
+impl std::error::Error for ParseError {}
+
+// The keys a metadata header may set, named after the matching command line flags.
+const META_KEYS: [&str; 5] = [
+    "max-steps",
+    "timeout",
+    "max-output",
+    "lattice-limit",
+    "seed",
+];
+
+/// Settings a program declares for itself in its metadata header.
+///
+/// The header is the run of comment lines at the very top of the source whose
+/// text starts with `velo:`, followed by whitespace-separated `key=value`
+/// pairs, e.g. `# velo: max-steps=1000 timeout=500`. The keys are those of
+/// the matching command line flags, which take precedence. Header lines are
+/// not rows of the grid, so the program still starts at the first line after them.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ProgramMeta {
+    max_steps: Option<u64>,
+    // In milliseconds.
+    timeout: Option<u64>,
+    max_output: Option<u64>,
+    lattice_limit: Option<usize>,
+    seed: Option<u64>,
+}
+
+impl ProgramMeta {
+    pub fn max_steps(&self) -> Option<u64> {
+        self.max_steps
+    }
+
+    /// The declared time limit, in milliseconds.
+    pub fn timeout(&self) -> Option<u64> {
+        self.timeout
+    }
+
+    pub fn max_output(&self) -> Option<u64> {
+        self.max_output
+    }
+
+    pub fn lattice_limit(&self) -> Option<usize> {
+        self.lattice_limit
+    }
+
+    pub fn seed(&self) -> Option<u64> {
+        self.seed
+    }
+
+    /// Renders the settings as a single header line starting with
+    /// `comment_char`, or nothing if none are set.
+    pub fn header(&self, comment_char: char) -> String {
+        let values = [
+            self.max_steps,
+            self.timeout,
+            self.max_output,
+            self.lattice_limit.map(|cells| cells as u64),
+            self.seed,
+        ];
+        let pairs: Vec<String> = META_KEYS
+            .iter()
+            .zip(values)
+            .filter_map(|(key, value)| Some(format!("{}={}", key, value?)))
+            .collect();
+        if pairs.is_empty() {
+            String::new()
+        } else {
+            format!("{} velo: {}\n", comment_char, pairs.join(" "))
+        }
+    }
+
+    /// Sets the pairs of one header line, the text after `velo:`.
+    fn read_pairs(&mut self, pairs: &str) -> Option<()> {
+        for pair in pairs.split_whitespace() {
+            let (key, value) = pair.split_once('=')?;
+            match key {
+                "max-steps" => self.max_steps = Some(value.parse().ok()?),
+                "timeout" => self.timeout = Some(value.parse().ok()?),
+                "max-output" => self.max_output = Some(value.parse().ok()?),
+                "lattice-limit" => self.lattice_limit = Some(value.parse().ok()?),
+                "seed" => self.seed = Some(value.parse().ok()?),
+                _ => return None,
+            }
+        }
+        Some(())
+    }
+}
+
+/// Options controlling how Velo source text is materialized into a Cosmos.
+#[derive(Debug, Clone)]
+pub struct ParseOptions {
+    // Characters that start a comment running to the end of the line.
+    comment_chars: Vec<char>,
+    // Swap rows and columns after parsing (see `Cosmos::transpose`).
+    transpose: bool,
+    // Largest allowed `width * height`; unbounded if `None`.
+    max_grid_cells: Option<usize>,
+    // Reject non-ASCII characters in program text instead of reading them as Void.
+    ascii_only: bool,
+    // Count comments toward the grid width, as if they were Void.
+    comments_in_width: bool,
+}
+
+impl Default for ParseOptions {
+    fn default() -> Self {
+        Self {
+            comment_chars: vec!['#'],
+            transpose: false,
+            max_grid_cells: None,
+            ascii_only: false,
+            comments_in_width: false,
+        }
+    }
+}
+
+impl ParseOptions {
+    /// Replaces the set of comment characters (only `#` by default).
+    pub fn with_comment_chars(mut self, comment_chars: impl IntoIterator<Item = char>) -> Self {
+        self.comment_chars = comment_chars.into_iter().collect();
+        self
+    }
+
+    /// Transposes the parsed grid, for programs written along columns.
+    pub fn with_transpose(mut self, transpose: bool) -> Self {
+        self.transpose = transpose;
+        self
+    }
+
+    /// Rejects sources whose grid would exceed `limit` cells, before allocating it.
+    ///
+    /// Unbounded by default; embedders parsing untrusted sources should set one.
+    pub fn with_max_grid_cells(mut self, limit: usize) -> Self {
+        self.max_grid_cells = Some(limit);
+        self
+    }
+
+    /// Rejects program text containing non-ASCII characters, such as pasted
+    /// smart quotes or dashes, which would otherwise silently become Void.
+    /// Comments are not checked.
+    pub fn with_ascii_only(mut self, ascii_only: bool) -> Self {
+        self.ascii_only = ascii_only;
+        self
+    }
+
+    /// Makes the grid as wide as the longest line including its comment, so
+    /// the edges of the grid match the visual layout of the source and the
+    /// columns under a comment are Void rather than outside the Cosmos.
+    /// Otherwise only the text before the comments counts.
+    pub fn with_comments_in_width(mut self, comments_in_width: bool) -> Self {
+        self.comments_in_width = comments_in_width;
+        self
+    }
+
+    pub fn comment_chars(&self) -> &[char] {
+        &self.comment_chars
+    }
+
+    pub fn transpose(&self) -> bool {
+        self.transpose
+    }
+
+    pub fn max_grid_cells(&self) -> Option<usize> {
+        self.max_grid_cells
+    }
+
+    /// The text after `velo:` if `line` is a metadata comment.
+    fn meta_pairs<'a>(&self, line: &'a str) -> Option<&'a str> {
+        let mut chars = line.chars();
+        if !chars
+            .next()
+            .is_some_and(|c| self.comment_chars.contains(&c))
+        {
+            return None;
+        }
+        chars.as_str().trim_start().strip_prefix("velo:")
+    }
+
+    /// The comment of each row of the grid `source` parses into, from its
+    /// comment character to the end of the line. The metadata header is not
+    /// part of the grid, and rows of a transposed grid have no comments.
+    pub fn comments<'a>(&self, source: &'a str) -> Vec<Option<&'a str>> {
+        if self.transpose {
+            return Vec::new();
+        }
+        source
+            .lines()
+            .skip_while(|line| self.meta_pairs(line).is_some())
+            .map(|line| {
+                let code = self.strip_comment(line);
+                Some(line[code.len()..].trim_end()).filter(|comment| !comment.is_empty())
+            })
+            .collect()
+    }
+
+    /// Strips everything from the first comment character onward.
+    fn strip_comment<'a>(&self, line: &'a str) -> &'a str {
+        match line.find(|c| self.comment_chars.contains(&c)) {
+            Some(index) => &line[..index],
+            None => line,
+        }
+    }
+}
+
+impl Cosmos {
+    /// Parses Velo source code with the default options.
+    pub fn parse(source: &str) -> Result<Cosmos, ParseError> {
+        Self::parse_with(source, &ParseOptions::default())
+    }
+
+    /// Parses Velo source code, one line of text per row of Runes.
+    pub fn parse_with(source: &str, options: &ParseOptions) -> Result<Cosmos, ParseError> {
+        Self::parse_with_meta(source, options).map(|(cosmos, _)| cosmos)
+    }
+
+    /// Parses Velo source code like `parse_with`, also returning the settings
+    /// of its metadata header.
+    pub fn parse_with_meta(
+        source: &str,
+        options: &ParseOptions,
+    ) -> Result<(Cosmos, ProgramMeta), ParseError> {
+        let mut meta = ProgramMeta::default();
+        let mut header_lines = 0;
+        for line in source.lines() {
+            let Some(pairs) = options.meta_pairs(line) else {
+                break;
+            };
+            header_lines += 1;
+            meta.read_pairs(pairs)
+                .ok_or(ParseError::InvalidMeta { line: header_lines })?;
+        }
+
+        let lines: Vec<&str> = source
+            .lines()
+            .skip(header_lines)
+            .map(|line| options.strip_comment(line))
+            .collect();
+
+        if options.ascii_only {
+            for (index, line) in lines.iter().enumerate() {
+                if let Some((column, character)) =
+                    line.chars().enumerate().find(|(_, c)| !c.is_ascii())
+                {
+                    return Err(ParseError::NonAscii {
+                        line: header_lines + index + 1,
+                        column: column + 1,
+                        character,
+                    });
+                }
+            }
+        }
+
+        let height = lines.len();
+        let width = if options.comments_in_width {
+            source
+                .lines()
+                .skip(header_lines)
+                .map(|line| line.chars().count())
+                .max()
+        } else {
+            lines.iter().map(|line| line.chars().count()).max()
+        }
+        .unwrap_or(0);
+
+        // Check the size before the grid is allocated.
+        if let Some(limit) = options.max_grid_cells {
+            let cells = width.saturating_mul(height);
+            if cells > limit {
+                return Err(ParseError::TooLarge { cells, limit });
+            }
+        }
+
+        let runes: Vec<Vec<Rune>> = lines
+            .iter()
+            .map(|line| line.chars().map(Rune::from_char).collect())
+            .collect();
+
+        let cosmos = Cosmos::new(runes, width, height);
+
+        if options.transpose {
+            Ok((cosmos.transpose(), meta))
+        } else {
+            Ok((cosmos, meta))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn comments_start_with_any_comment_char() {
+        let options = ParseOptions::default().with_comment_chars(['#', ';']);
+        let cosmos = Cosmos::parse_with(">+. ; print\n v # turn", &options).unwrap();
+        assert_eq!((cosmos.width(), cosmos.height()), (4, 2));
+        assert_eq!(cosmos.get(2, 0), Rune::Output);
+        assert_eq!(cosmos.get(3, 0), Rune::Void);
+        assert_eq!(cosmos.get(1, 1), Rune::ThrustDown);
+    }
+
+    #[test]
+    fn only_hash_starts_a_comment_by_default() {
+        let cosmos = Cosmos::parse(">; #.").unwrap();
+        assert_eq!(cosmos.width(), 3);
+        assert_eq!(cosmos.get(1, 0), Rune::Void);
+        assert_eq!(ParseOptions::default().comment_chars(), ['#']);
+    }
+
+    #[test]
+    fn transposed_columns_parse_like_the_matching_rows() {
+        let options = ParseOptions::default().with_transpose(true);
+        let columns = Cosmos::parse_with("v \n+<\n.^", &options).unwrap();
+        let rows = Cosmos::parse(">+.\n ^<").unwrap();
+        assert_eq!(columns.to_string(), rows.to_string());
+    }
+
+    #[test]
+    fn grids_over_the_cell_limit_are_rejected() {
+        let source = ">+.\nv";
+        let options = ParseOptions::default().with_max_grid_cells(6);
+        assert!(Cosmos::parse_with(source, &options).is_ok());
+
+        let options = ParseOptions::default().with_max_grid_cells(5);
+        assert_eq!(
+            Cosmos::parse_with(source, &options).err(),
+            Some(ParseError::TooLarge { cells: 6, limit: 5 })
+        );
+    }
+
+    #[test]
+    fn ascii_only_reports_the_first_non_ascii_character() {
+        let options = ParseOptions::default().with_ascii_only(true);
+        let source = ">+.\n +\u{201c}. # \u{201c}quoted\u{201d}";
+        assert_eq!(
+            Cosmos::parse_with(source, &options).err(),
+            Some(ParseError::NonAscii {
+                line: 2,
+                column: 3,
+                character: '\u{201c}'
+            })
+        );
+        // Comments may use any characters.
+        assert!(Cosmos::parse_with(">+. # \u{201c}quoted\u{201d}", &options).is_ok());
+    }
+
+    #[test]
+    fn metadata_headers_are_read_and_written_back() {
+        let source = "# velo: max-steps=1000 timeout=500\n# velo: seed=7\n>+.\n";
+        let (cosmos, meta) = Cosmos::parse_with_meta(source, &ParseOptions::default()).unwrap();
+        assert_eq!(cosmos.to_string(), ">+.\n");
+        assert_eq!(meta.max_steps(), Some(1000));
+        assert_eq!(meta.timeout(), Some(500));
+        assert_eq!(meta.seed(), Some(7));
+        assert_eq!(meta.max_output(), None);
+        assert_eq!(
+            meta.header('#'),
+            "# velo: max-steps=1000 timeout=500 seed=7\n"
+        );
+        assert_eq!(ProgramMeta::default().header('#'), "");
+
+        for header in ["# velo: speed=3\n>", "# velo: seed=7\n# velo: seed\n>"] {
+            let error = Cosmos::parse_with_meta(header, &ParseOptions::default()).err();
+            let line = header.lines().count() - 1;
+            assert_eq!(error, Some(ParseError::InvalidMeta { line }));
+        }
+    }
+
+    #[test]
+    fn comments_can_count_towards_the_width() {
+        let source = ">+. # print\n v";
+        assert_eq!(Cosmos::parse(source).unwrap().width(), 4);
+        let options = ParseOptions::default().with_comments_in_width(true);
+        let cosmos = Cosmos::parse_with(source, &options).unwrap();
+        assert_eq!(cosmos.width(), 11);
+        assert_eq!(cosmos.to_string(), ">+.        \n v         \n");
+    }
+}
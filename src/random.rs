@@ -0,0 +1,63 @@
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hasher};
+
+/// A small, deterministic pseudo random number generator (SplitMix64).
+///
+/// Velo only needs a handful of random choices per run, so a tiny generator
+/// keeps runs reproducible from a single seed without extra dependencies.
+#[derive(Debug, Clone)]
+pub struct Rng {
+    state: u64,
+}
+
+impl Rng {
+    pub fn from_seed(seed: u64) -> Self {
+        Self { state: seed }
+    }
+
+    /// Seeds the generator with entropy from the OS.
+    pub fn from_entropy() -> Self {
+        // `RandomState` is keyed with random bits obtained from the OS.
+        Self::from_seed(RandomState::new().build_hasher().finish())
+    }
+
+    pub(crate) fn state(&self) -> u64 {
+        self.state
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.state = self.state.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        let mut z = self.state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^ (z >> 31)
+    }
+
+    /// Returns a value in `0..bound`. `bound` must be positive.
+    pub fn below(&mut self, bound: u64) -> u64 {
+        self.next_u64() % bound
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn the_same_seed_gives_the_same_sequence() {
+        let mut first = Rng::from_seed(42);
+        let mut second = Rng::from_seed(42);
+        for _ in 0..100 {
+            assert_eq!(first.next_u64(), second.next_u64());
+        }
+        assert_ne!(Rng::from_seed(1).next_u64(), Rng::from_seed(2).next_u64());
+    }
+
+    #[test]
+    fn below_stays_under_the_bound() {
+        let mut rng = Rng::from_seed(7);
+        for bound in 1..50 {
+            assert!(rng.below(bound) < bound);
+        }
+    }
+}
@@ -1,16 +1,382 @@
-use crate::models::{Cosmos, Rune, Vessel};
+use std::fmt;
+use std::io;
+use std::process::ExitCode;
+use std::str::FromStr;
+use std::time::Duration;
+
+use crate::machine::Machine;
+use crate::models::{Cosmos, DEFAULT_LATTICE_CELLS, Vessel};
+use crate::parse::{ParseError, ParseOptions};
+
+/// The process exit status for failures before a program runs: a flag, file
+/// or savefile that could not be used, or a failed `--check`.
+pub const SETUP_FAILURE: u8 = 64;
 
 /// Defines the reason for the Velo program's execution halt.
+///
+/// New reasons may be added; `is_success` and `exit_code` classify any of them.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
 pub enum Termination {
-    Stopped,                      // Vessel velocity/pointer reached zero.
-    NoSignal(usize, usize),       // Vessel traveled out of the Cosmos bounds.
+    Stopped,                // Vessel velocity/pointer reached zero.
+    NoSignal(usize, usize), // Vessel traveled out of the Cosmos bounds.
+    StuckOscillation,       // Vessel bounced between two cells without changing any state.
+    Exited(u8),             // An Exit rune halted the program with this exit code.
+    LatticeLimit, // The Vessel needed more data cells than `Config::with_lattice_limit` allows.
+    StepLimit,    // The run took the cycles allowed by `Config::with_max_steps`.
+    Timeout,      // The run took longer than `Config::with_timeout` allows.
+    OutputLimit,  // The program wrote more than `Config::with_output_limit` allows.
+}
+
+impl Termination {
+    /// Whether the program ended normally: the Vessel stopped, or exited with code 0.
+    pub fn is_success(&self) -> bool {
+        matches!(self, Self::Stopped | Self::Exited(0))
+    }
+
+    /// The process exit status for this outcome: the Exit rune's code (at most
+    /// `MAX_EXIT_CODE`), 0 for a stopped Vessel, and a distinct status from 67
+    /// up for each failure. 64 to 66 are left for errors before the run; see
+    /// `SETUP_FAILURE` and `SailError::status`.
+    pub fn status(&self) -> u8 {
+        match self {
+            Self::Stopped => 0,
+            Self::Exited(code) => *code,
+            Self::NoSignal(..) => 67,
+            Self::StepLimit => 68,
+            Self::Timeout => 69,
+            Self::StuckOscillation => 70,
+            Self::LatticeLimit => 71,
+            Self::OutputLimit => 72,
+        }
+    }
+
+    /// The process exit code for this outcome, see `status`.
+    pub fn exit_code(&self) -> ExitCode {
+        ExitCode::from(self.status())
+    }
+}
+
+/// Structural problems that prevent a Velo program from running at all.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SailError {
+    EmptyCosmos,                  // The Cosmos has no Runes.
     NoInitialVelocityOrDirection, // Start Rune was not a Thrust rune.
+    ParkingStart,                 // Start Rune was Parking, which sets no direction.
+    Parse(ParseError),            // The source code could not be parsed.
+}
+
+impl From<ParseError> for SailError {
+    fn from(error: ParseError) -> Self {
+        Self::Parse(error)
+    }
+}
+
+impl fmt::Display for SailError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::EmptyCosmos => write!(f, "The cosmos is empty."),
+            Self::NoInitialVelocityOrDirection => write!(
+                f,
+                "Here was no Thrust rune at the top left corner of the cosmos."
+            ),
+            Self::ParkingStart => write!(
+                f,
+                "The cosmos starts with a Parking rune, which gives the vessel no direction. Start with a Thrust rune instead."
+            ),
+            Self::Parse(error) => write!(f, "{}", error),
+        }
+    }
+}
+
+impl std::error::Error for SailError {}
+
+impl SailError {
+    /// A machine-readable identifier for the error.
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::EmptyCosmos => "empty_cosmos",
+            Self::NoInitialVelocityOrDirection => "no_initial_velocity_or_direction",
+            Self::ParkingStart => "parking_start",
+            Self::Parse(ParseError::TooLarge { .. }) => "too_large",
+            Self::Parse(ParseError::NonAscii { .. }) => "non_ascii",
+            Self::Parse(ParseError::InvalidMeta { .. }) => "invalid_meta",
+        }
+    }
+
+    /// The process exit status for the error: 65 for source that could not be
+    /// parsed, 66 for a program that cannot start.
+    pub fn status(&self) -> u8 {
+        match self {
+            Self::Parse(_) => 65,
+            _ => 66,
+        }
+    }
+
+    /// The process exit code for the error, see `status`.
+    pub fn exit_code(&self) -> ExitCode {
+        ExitCode::from(self.status())
+    }
+
+    /// Renders the error as a JSON object, e.g. `{"error":"empty_cosmos","status":66}`.
+    pub fn to_json(&self) -> String {
+        format!(
+            "{{\"error\":\"{}\",\"status\":{}}}",
+            self.name(),
+            self.status()
+        )
+    }
+}
+
+/// Describes how a Velo program that did run came to an end.
+#[derive(Debug, Clone)]
+pub struct CompletionReport {
+    termination: Termination,
+    // Execution cycles taken, counted per Vessel. A run of skipped Void runes counts as one cycle.
+    steps: u64,
+    // Bytes written by the output Runes.
+    output_bytes: u64,
+    // Times the Vessel's data lattice had to grow.
+    lattice_growths: u64,
+    // Largest number of data cells the Vessel allocated.
+    peak_lattice_cells: usize,
+    // Final state of the Vessel.
+    x: usize,
+    y: usize,
+    velocity: usize,
+}
+
+impl CompletionReport {
+    pub(crate) fn new(
+        termination: Termination,
+        steps: u64,
+        output_bytes: u64,
+        vessel: &Vessel,
+    ) -> Self {
+        Self {
+            termination,
+            steps,
+            output_bytes,
+            lattice_growths: vessel.lattice_growths(),
+            peak_lattice_cells: vessel.lattice_len(),
+            x: vessel.x(),
+            y: vessel.y(),
+            velocity: vessel.velocity(),
+        }
+    }
+
+    pub fn termination(&self) -> Termination {
+        self.termination
+    }
+
+    pub fn steps(&self) -> u64 {
+        self.steps
+    }
+
+    pub fn output_bytes(&self) -> u64 {
+        self.output_bytes
+    }
+
+    pub fn lattice_growths(&self) -> u64 {
+        self.lattice_growths
+    }
+
+    pub fn peak_lattice_cells(&self) -> usize {
+        self.peak_lattice_cells
+    }
+
+    pub fn x(&self) -> usize {
+        self.x
+    }
+
+    pub fn y(&self) -> usize {
+        self.y
+    }
+
+    pub fn velocity(&self) -> usize {
+        self.velocity
+    }
+
+    /// Renders the report as a single-line JSON object for automated tooling.
+    pub fn to_json(&self) -> String {
+        let termination = match self.termination {
+            Termination::Stopped => "{\"type\":\"stopped\"}".to_string(),
+            Termination::NoSignal(x, y) => {
+                format!("{{\"type\":\"no_signal\",\"x\":{},\"y\":{}}}", x, y)
+            }
+            Termination::StuckOscillation => "{\"type\":\"stuck_oscillation\"}".to_string(),
+            Termination::Exited(code) => format!("{{\"type\":\"exited\",\"code\":{}}}", code),
+            Termination::LatticeLimit => "{\"type\":\"lattice_limit\"}".to_string(),
+            Termination::StepLimit => "{\"type\":\"step_limit\"}".to_string(),
+            Termination::Timeout => "{\"type\":\"timeout\"}".to_string(),
+            Termination::OutputLimit => "{\"type\":\"output_limit\"}".to_string(),
+        };
+
+        format!(
+            "{{\"termination\":{},\"status\":{},\"steps\":{},\"output_bytes\":{},\"lattice_growths\":{},\"peak_lattice_cells\":{},\"x\":{},\"y\":{},\"velocity\":{}}}",
+            termination,
+            self.termination.status(),
+            self.steps,
+            self.output_bytes,
+            self.lattice_growths,
+            self.peak_lattice_cells,
+            self.x,
+            self.y,
+            self.velocity
+        )
+    }
+}
+
+/// How trace lines are printed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum TraceFormat {
+    #[default]
+    Debug, // The full `{:?}` dump of the Vessel.
+    Table, // Aligned columns: step, position, direction, velocity, rune and cell value.
+}
+
+impl FromStr for TraceFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "debug" => Ok(Self::Debug),
+            "table" => Ok(Self::Table),
+            _ => Err(format!(
+                "unknown trace format `{}` (expected debug or table)",
+                s
+            )),
+        }
+    }
+}
+
+/// How the Output rune (`.`) writes a cell value.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum OutputEncoding {
+    #[default]
+    Utf8, // The code point as UTF-8, so values of 128 and above take several bytes.
+    Latin1,     // Values up to 255 as a single byte; larger values are not printed.
+    RawLowByte, // The lowest byte of the value, whatever it is.
+}
+
+impl FromStr for OutputEncoding {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "utf8" => Ok(Self::Utf8),
+            "latin1" => Ok(Self::Latin1),
+            "raw-low-byte" => Ok(Self::RawLowByte),
+            _ => Err(format!(
+                "unknown output encoding `{}` (expected utf8, latin1 or raw-low-byte)",
+                s
+            )),
+        }
+    }
 }
 
+/// What happens when the Vessel would leave the Cosmos.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum EdgePolicy {
+    #[default]
+    Terminate, // The run ends with `NoSignal`.
+    Wrap,   // The Vessel re-enters from the opposite edge.
+    Bounce, // The Vessel turns around on the edge cell.
+}
+
+impl FromStr for EdgePolicy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "terminate" => Ok(Self::Terminate),
+            "wrap" => Ok(Self::Wrap),
+            "bounce" => Ok(Self::Bounce),
+            _ => Err(format!(
+                "unknown edge policy `{}` (expected terminate, wrap or bounce)",
+                s
+            )),
+        }
+    }
+}
+
+/// When program output is flushed to its destination.
+///
+/// Output is always flushed before the program reads input and when it halts.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum FlushPolicy {
+    EveryByte, // After every write, for interactive programs.
+    OnNewline, // After every write containing a newline, like a terminal.
+    #[default]
+    OnTermination, // Only when required, which is fastest for batch runs.
+}
+
+impl FromStr for FlushPolicy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "every-byte" => Ok(Self::EveryByte),
+            "newline" => Ok(Self::OnNewline),
+            "termination" => Ok(Self::OnTermination),
+            _ => Err(format!(
+                "unknown flush policy `{}` (expected every-byte, newline or termination)",
+                s
+            )),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
 pub struct Config {
-    debug: bool,
-    trace: bool,
-    ignore_void: bool,
+    pub(crate) debug: bool,
+    pub(crate) trace: bool,
+    pub(crate) ignore_void: bool,
+    // Seed for the random Runes. `None` seeds from OS entropy.
+    pub(crate) seed: Option<u64>,
+    // Glide over runs of Void runes in a single cycle instead of one cycle per cell.
+    pub(crate) skip_void: bool,
+    pub(crate) trace_format: TraceFormat,
+    pub(crate) output_encoding: OutputEncoding,
+    pub(crate) flush_policy: FlushPolicy,
+    // Show positions in trace and debug output as 1-based `line:col`.
+    pub(crate) line_col: bool,
+    // Mark the cell at the pointer in trace and debug lattice dumps.
+    pub(crate) show_pointer: bool,
+    // Record impacted cells so `Machine::unused_runes` can list the rest.
+    pub(crate) track_unused: bool,
+    // Fixed size of the data lattice when the pointer wraps around it.
+    pub(crate) ring_lattice: Option<usize>,
+    // Consecutive oscillating cycles after which the run is stopped.
+    pub(crate) oscillation_limit: Option<u64>,
+    // Data cells a Vessel may allocate before the run is stopped.
+    pub(crate) lattice_limit: Option<usize>,
+    // Cycles, wall-clock time and output bytes after which the run is stopped.
+    pub(crate) max_steps: Option<u64>,
+    pub(crate) timeout: Option<Duration>,
+    pub(crate) output_limit: Option<u64>,
+    // Preset data cells used by the source entry points.
+    pub(crate) initial_lattice: Option<Vec<u32>>,
+    pub(crate) edge_policy: EdgePolicy,
+    // Move `velocity` cells per cycle instead of one.
+    pub(crate) velocity_is_step: bool,
+    // Pause between cycles in `run`, for watching a program live.
+    pub(crate) step_delay: Duration,
+    // Perpendicular thrust resets velocity to 1 as well as turning.
+    pub(crate) reset_velocity_on_turn: bool,
+    // The pointer addresses cell `velocity - 1` instead of cell `velocity`.
+    pub(crate) zero_based_pointer: bool,
+    // Data cells allocated up front for a Vessel without preset cells.
+    pub(crate) initial_lattice_capacity: usize,
+    // Steps `Machine::step_back` can undo.
+    pub(crate) history_depth: usize,
+}
+
+impl Default for Config {
+    /// No debug or trace output; every optional mode off.
+    fn default() -> Self {
+        Self::new(false, false, false)
+    }
 }
 
 impl Config {
@@ -19,48 +385,522 @@ impl Config {
             debug,
             trace,
             ignore_void,
+            seed: None,
+            skip_void: false,
+            trace_format: TraceFormat::Debug,
+            output_encoding: OutputEncoding::Utf8,
+            flush_policy: FlushPolicy::OnTermination,
+            line_col: false,
+            show_pointer: false,
+            track_unused: false,
+            ring_lattice: None,
+            oscillation_limit: None,
+            lattice_limit: None,
+            max_steps: None,
+            timeout: None,
+            output_limit: None,
+            initial_lattice: None,
+            edge_policy: EdgePolicy::Terminate,
+            velocity_is_step: false,
+            step_delay: Duration::ZERO,
+            reset_velocity_on_turn: false,
+            zero_based_pointer: false,
+            initial_lattice_capacity: DEFAULT_LATTICE_CELLS,
+            history_depth: 0,
         }
     }
+
+    /// Makes runs using random Runes reproducible.
+    pub fn with_seed(mut self, seed: u64) -> Self {
+        self.seed = Some(seed);
+        self
+    }
+
+    /// Lets the Vessel cross a whole run of Void runes in a single cycle.
+    ///
+    /// Void runes have no effect, so the program's behavior is unchanged. Only
+    /// traces that print Void impacts (`trace` without `ignore_void`) would
+    /// differ, so the fast path is not taken for them. Neither is it while
+    /// spawned Vessels sail, as they would fall out of step, or in programs
+    /// with an Output Cycle rune (`$`), whose count it would change.
+    pub fn with_skip_void(mut self, skip_void: bool) -> Self {
+        self.skip_void = skip_void;
+        self
+    }
+
+    pub fn with_trace_format(mut self, trace_format: TraceFormat) -> Self {
+        self.trace_format = trace_format;
+        self
+    }
+
+    /// Chooses how the Output rune writes cell values; UTF-8 by default.
+    pub fn with_output_encoding(mut self, encoding: OutputEncoding) -> Self {
+        self.output_encoding = encoding;
+        self
+    }
+
+    /// Chooses when program output is flushed. By default it is only flushed
+    /// when needed: before reading input, before debug output and at the end.
+    pub fn with_flush_policy(mut self, flush_policy: FlushPolicy) -> Self {
+        self.flush_policy = flush_policy;
+        self
+    }
+
+    /// Prefixes trace and debug lines with the Vessel's 1-based `line:col`, as
+    /// an editor shows it, and makes the table trace print line and column
+    /// instead of the 0-based `x` and `y`.
+    pub fn with_line_col(mut self, line_col: bool) -> Self {
+        self.line_col = line_col;
+        self
+    }
+
+    /// Marks the cell the pointer addresses in the lattice printed by traces
+    /// and the Debug rune, e.g. `[0, 3, (5), 0]`.
+    pub fn with_show_pointer(mut self, show_pointer: bool) -> Self {
+        self.show_pointer = show_pointer;
+        self
+    }
+
+    /// Records which cells are impacted during the run, so
+    /// `Machine::unused_runes` can report the Runes never reached.
+    pub fn with_unused_tracking(mut self, track_unused: bool) -> Self {
+        self.track_unused = track_unused;
+        self
+    }
+
+    /// Gives the Vessel a fixed lattice of `size` cells (at least 1).
+    ///
+    /// The data pointer (velocity) addresses cell `velocity % size`, so memory
+    /// never grows. Velocity itself is unchanged and still halts at 0.
+    pub fn with_ring_lattice(mut self, size: usize) -> Self {
+        self.ring_lattice = Some(size);
+        self
+    }
+
+    /// Stops the run with `Termination::StuckOscillation` once the Vessel has
+    /// alternated between the same two cells for `cycles` consecutive cycles
+    /// without changing its velocity or any data cell.
+    pub fn with_oscillation_detection(mut self, cycles: u64) -> Self {
+        self.oscillation_limit = Some(cycles);
+        self
+    }
+
+    /// Stops the run with `Termination::LatticeLimit` once a Vessel addresses
+    /// a cell past the first `cells`, or starts with more cells than that.
+    /// The lattice is never grown past the limit, so no pointer value can
+    /// exhaust memory.
+    pub fn with_lattice_limit(mut self, cells: usize) -> Self {
+        self.lattice_limit = Some(cells);
+        self
+    }
+
+    /// Stops the run with `Termination::StepLimit` after `steps` cycles,
+    /// counted as in `CompletionReport::steps`. Checked after every cycle.
+    pub fn with_max_steps(mut self, steps: u64) -> Self {
+        self.max_steps = Some(steps);
+        self
+    }
+
+    /// Stops the run with `Termination::Timeout` once it has taken `timeout`
+    /// since the first step. Checked after every cycle.
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = Some(timeout);
+        self
+    }
+
+    /// Stops the run with `Termination::OutputLimit` once the program has
+    /// written more than `bytes` bytes of output. The cycle that crosses the
+    /// limit still writes its output.
+    pub fn with_output_limit(mut self, bytes: u64) -> Self {
+        self.output_limit = Some(bytes);
+        self
+    }
+
+    /// A configuration for running untrusted programs, with every run bounded:
+    ///
+    /// - at most 10 000 000 cycles (`with_max_steps`),
+    /// - at most 5 seconds (`with_timeout`),
+    /// - at most 65 536 data cells per Vessel (`with_lattice_limit`),
+    /// - at most 1 MiB of output (`with_output_limit`),
+    /// - no step history (`with_history`), since it copies the Cosmos every cycle.
+    ///
+    /// Any of them can be changed with the usual builder methods afterwards.
+    /// Velo never opens files on its own: input comes from stdin unless the
+    /// host calls `Machine::set_input`. The grid size is a parsing concern; pair
+    /// this with `ParseOptions::with_max_grid_cells` for untrusted sources.
+    pub fn sandboxed() -> Self {
+        Self::default()
+            .with_max_steps(10_000_000)
+            .with_timeout(Duration::from_secs(5))
+            .with_lattice_limit(65_536)
+            .with_output_limit(1 << 20)
+    }
+
+    /// Allocates `cells` data cells up front (16 by default), so programs known
+    /// to use many cells do not grow the lattice repeatedly. Applies to the
+    /// source entry points and `Machine::new_from`, unless an initial lattice is set.
+    pub fn with_initial_lattice_capacity(mut self, cells: usize) -> Self {
+        self.initial_lattice_capacity = cells;
+        self
+    }
+
+    /// Starts `run_source` programs with these data cells (`cells[0]` is cell 0).
+    pub fn with_initial_lattice(mut self, cells: Vec<u32>) -> Self {
+        self.initial_lattice = Some(cells);
+        self
+    }
+
+    /// Chooses what happens when the Vessel would leave the Cosmos.
+    ///
+    /// `Terminate` (the default) ends the run with `NoSignal`. `Wrap` moves it
+    /// to the opposite edge, as if the grid were a torus. `Bounce` turns it
+    /// around on the edge cell, which takes a cycle without impacting a Rune.
+    pub fn with_edge_policy(mut self, edge_policy: EdgePolicy) -> Self {
+        self.edge_policy = edge_policy;
+        self
+    }
+
+    /// Moves the Vessel `velocity` cells per cycle in its direction instead of one.
+    ///
+    /// Only the landing cell is impacted; the cells jumped over are not. A
+    /// skip passes over the next landing cell. A jump that would land outside
+    /// the Cosmos ends the run with `NoSignal` at the edge cell on the way.
+    /// Gliding over Void runs (`with_skip_void`) is not used in this mode.
+    pub fn with_velocity_is_step(mut self, velocity_is_step: bool) -> Self {
+        self.velocity_is_step = velocity_is_step;
+        self
+    }
+
+    /// Sleeps for `delay` after every cycle of `Machine::run`. Zero (the default) never sleeps.
+    pub fn with_step_delay(mut self, delay: Duration) -> Self {
+        self.step_delay = delay;
+        self
+    }
+
+    /// Keeps the state before each of the last `depth` steps, so
+    /// `Machine::step_back` can undo them. Every recorded step copies the
+    /// Cosmos and the fleet, so a short history suits large programs best.
+    /// Zero (the default) records nothing.
+    pub fn with_history(mut self, depth: usize) -> Self {
+        self.history_depth = depth;
+        self
+    }
+
+    /// Makes a perpendicular Thrust rune reset the velocity (pointer) to 1 as
+    /// well as turning, as if the Vessel parked on every turn. Off by default,
+    /// where turning leaves the velocity unchanged.
+    pub fn with_reset_velocity_on_turn(mut self, reset: bool) -> Self {
+        self.reset_velocity_on_turn = reset;
+        self
+    }
+
+    /// Makes the pointer address cell `velocity - 1`, so a Vessel starting
+    /// at velocity 1 works on cell 0 instead of leaving it unused. Velocity 0
+    /// still halts the Vessel. Lattice indices read from cells, as by `@`,
+    /// are not shifted.
+    pub fn with_zero_based_pointer(mut self, zero_based: bool) -> Self {
+        self.zero_based_pointer = zero_based;
+        self
+    }
 }
 
 /// Runs the Velo program by moving the Vessel through the Cosmos grid.
-pub fn sail(cosmos: Cosmos, mut vessel: Vessel, config: Config) -> Termination {
-    let width = cosmos.width();
-    let height = cosmos.height();
+pub fn sail(cosmos: Cosmos, vessel: Vessel, config: Config) -> Result<CompletionReport, SailError> {
+    Ok(Machine::new(cosmos, vessel, config)?.run())
+}
+
+/// Runs the program with output and warnings discarded and no trace or debug printing.
+///
+/// Meant for measuring interpreter throughput and for programs run only for
+/// their final state. Returns how the run ended and the cycles it took.
+pub fn run_headless(cosmos: Cosmos, vessel: Vessel) -> Result<(Termination, u64), SailError> {
+    let mut machine = Machine::new(cosmos, vessel, Config::default())?;
+    machine.set_output(io::sink());
+    machine.set_diagnostic_output(io::sink());
+    let report = machine.run();
+
+    Ok((report.termination(), report.steps()))
+}
+
+/// Parses Velo source code and sails it from the top left corner, using stdin and stdout.
+pub fn run_source(source: &str, config: Config) -> Result<CompletionReport, SailError> {
+    run_source_with(source, &ParseOptions::default(), config)
+}
+
+/// Like `run_source`, with explicit parsing options.
+pub fn run_source_with(
+    source: &str,
+    options: &ParseOptions,
+    config: Config,
+) -> Result<CompletionReport, SailError> {
+    let cosmos = Cosmos::parse_with(source, options)?;
+    let vessel = starting_vessel(&cosmos, &config);
+
+    sail(cosmos, vessel, config)
+}
 
-    // Check for initial velocity requirement (must start on a Thrust rune)
-    if vessel.velocity() == 0 {
-        return Termination::NoInitialVelocityOrDirection;
+/// The Vessel on the top left Rune, carrying the configured initial lattice.
+pub(crate) fn starting_vessel(cosmos: &Cosmos, config: &Config) -> Vessel {
+    let start_rune = cosmos.get(0, 0);
+    match &config.initial_lattice {
+        Some(cells) => Vessel::with_lattice(0, 0, start_rune, cells.clone()),
+        None => Vessel::with_capacity(0, 0, start_rune, config.initial_lattice_capacity),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::run;
+
+    fn sail_with(source: &str, config: Config) -> Termination {
+        run_source(source, config)
+            .expect("the program starts")
+            .termination()
     }
 
-    // The execution loop: continues as long as the Velocity/Pointer is positive.
-    while vessel.velocity() > 0 {
-        match vessel.get_next_coordinate() {
-            Ok((x, y)) => {
-                // Check if the next coordinates are within the Cosmos boundaries.
-                if x >= width || y >= height {
-                    return Termination::NoSignal(x.min(width - 1), y.min(height - 1));
-                }
+    fn sail_seeded(source: &str, seed: u64) -> Termination {
+        sail_with(source, Config::new(false, false, false).with_seed(seed))
+    }
 
-                let rune = cosmos.get(x, y);
+    #[test]
+    fn seeded_runs_are_reproducible() {
+        // `?` stops the Vessel when it picks Left and lets it leave the grid otherwise.
+        let outcomes: Vec<Termination> = (0..32).map(|seed| sail_seeded(">?", seed)).collect();
+        assert!(outcomes.contains(&Termination::Stopped));
+        assert!(outcomes.contains(&Termination::NoSignal(1, 0)));
+        for (seed, outcome) in outcomes.iter().enumerate() {
+            assert_eq!(&sail_seeded(">?", seed as u64), outcome);
+        }
+    }
 
-                // Update the vessel's position.
-                vessel.move_to(x, y);
+    #[test]
+    fn skip_void_keeps_the_outcome() {
+        let programs = [
+            (">     ", Termination::NoSignal(5, 0)),
+            (">   v\n\n    <", Termination::NoSignal(0, 2)),
+            (">  <", Termination::Stopped),
+        ];
+        for (source, expected) in programs {
+            let skipping = Config::new(false, false, false).with_skip_void(true);
+            assert_eq!(sail_with(source, skipping), expected, "{:?}", source);
+            let stepping = Config::new(false, false, false);
+            assert_eq!(sail_with(source, stepping), expected, "{:?}", source);
+        }
+    }
 
-                // Impact the Rune and execute the associated instruction/movement.
-                vessel.impact_rune(rune);
+    #[test]
+    fn store_velocity_makes_the_cell_non_zero() {
+        // `]` only turns (here off the bottom edge) when the current cell is not zero.
+        let config = || Config::new(false, false, false);
+        assert_eq!(sail_with("> ]  ", config()), Termination::NoSignal(4, 0));
+        assert_eq!(sail_with(">V]  ", config()), Termination::NoSignal(2, 0));
+    }
 
-                if rune == Rune::Debug && config.debug {
-                    println!("[Debug] Vessel: {:?}. Rune: {:?}", vessel, rune);
-                }
+    #[test]
+    fn sail_reports_why_a_program_cannot_start() {
+        let config = || Config::new(false, false, false);
+        assert_eq!(run_source("", config()).err(), Some(SailError::EmptyCosmos));
+        assert_eq!(
+            run_source("+>", config()).err(),
+            Some(SailError::NoInitialVelocityOrDirection)
+        );
+    }
 
-                if config.trace && !(config.ignore_void && rune == Rune::Void) {
-                    println!("Vessel: {:?}. Rune: {:?}", vessel, rune);
-                }
-            }
-            Err(_) => return Termination::NoSignal(vessel.x(), vessel.y()),
+    #[test]
+    fn sail_counts_the_steps_taken() {
+        let report = run_source(">++  ", Config::new(false, false, false)).expect("runs");
+        assert_eq!(report.termination(), Termination::NoSignal(4, 0));
+        assert_eq!(report.steps(), 5);
+    }
+
+    #[test]
+    fn skip_void_counts_a_run_of_void_as_one_step() {
+        let config = Config::new(false, false, false).with_skip_void(true);
+        let report = run_source(">+    +  ", config).expect("runs");
+        assert_eq!(report.termination(), Termination::NoSignal(8, 0));
+        assert_eq!(report.steps(), 5);
+    }
+
+    #[test]
+    fn trace_formats_parse_from_their_names() {
+        assert_eq!("debug".parse(), Ok(TraceFormat::Debug));
+        assert_eq!("table".parse(), Ok(TraceFormat::Table));
+        assert!("json".parse::<TraceFormat>().is_err());
+    }
+
+    #[test]
+    fn summaries_render_as_json() {
+        let report = run_source(">++  ", Config::new(false, false, false)).expect("runs");
+        assert_eq!(
+            report.to_json(),
+            "{\"termination\":{\"type\":\"no_signal\",\"x\":4,\"y\":0},\"status\":67,\"steps\":5,\"output_bytes\":0,\"lattice_growths\":0,\"peak_lattice_cells\":16,\"x\":4,\"y\":0,\"velocity\":1}"
+        );
+        let error = run_source("+>", Config::new(false, false, false)).err();
+        assert_eq!(
+            error.map(|error| error.to_json()),
+            Some("{\"error\":\"no_initial_velocity_or_direction\",\"status\":66}".to_string())
+        );
+    }
+
+    #[test]
+    fn reverse_if_zero_turns_back_only_on_a_zero_cell() {
+        let config = || Config::new(false, false, false);
+        // Turned back, the Vessel meets the opposing `>` and stops.
+        assert_eq!(sail_with(">U  ", config()), Termination::Stopped);
+        assert_eq!(sail_with(">+U  ", config()), Termination::NoSignal(4, 0));
+    }
+
+    #[test]
+    fn run_source_parses_with_the_given_options() {
+        let config = || Config::new(false, false, false);
+        assert_eq!(sail_with(">+;+  ", config()), Termination::NoSignal(5, 0));
+
+        let options = ParseOptions::default().with_comment_chars([';']);
+        let report = run_source_with(">+;+  ", &options, config()).expect("runs");
+        assert_eq!(report.termination(), Termination::NoSignal(1, 0));
+    }
+
+    #[test]
+    fn programs_start_with_the_initial_lattice() {
+        // `[` turns off the top edge only when cell 1 is not zero.
+        let config = Config::new(false, false, false);
+        assert_eq!(
+            sail_with(">[  ", config.clone()),
+            Termination::NoSignal(3, 0)
+        );
+        let preset = config.with_initial_lattice(vec![0, 1]);
+        assert_eq!(sail_with(">[  ", preset), Termination::NoSignal(1, 0));
+    }
+
+    #[test]
+    fn run_source_reports_parse_errors() {
+        let options = ParseOptions::default().with_max_grid_cells(2);
+        let error = run_source_with(">+  ", &options, Config::new(false, false, false)).err();
+        assert_eq!(
+            error,
+            Some(SailError::Parse(ParseError::TooLarge {
+                cells: 4,
+                limit: 2
+            }))
+        );
+    }
+
+    #[test]
+    fn run_headless_reports_the_termination_and_cycles() {
+        let cosmos = Cosmos::parse(">+.").unwrap();
+        let vessel = Vessel::new(0, 0, cosmos.get(0, 0));
+        let outcome = run_headless(cosmos, vessel);
+        assert_eq!(outcome, Ok((Termination::NoSignal(2, 0), 3)));
+
+        let cosmos = Cosmos::parse("+>").unwrap();
+        let vessel = Vessel::new(0, 0, cosmos.get(0, 0));
+        let outcome = run_headless(cosmos, vessel);
+        assert_eq!(outcome, Err(SailError::NoInitialVelocityOrDirection));
+    }
+
+    #[test]
+    fn the_default_config_turns_every_mode_off() {
+        let config = Config::default();
+        assert!(!config.debug && !config.trace && !config.ignore_void);
+        assert!(!config.skip_void && !config.velocity_is_step);
+        assert_eq!(config.seed, None);
+        assert_eq!(config.trace_format, TraceFormat::Debug);
+        assert_eq!(config.ring_lattice, None);
+        assert_eq!(config.oscillation_limit, None);
+        assert_eq!(config.initial_lattice, None);
+        assert!(config.step_delay.is_zero());
+        assert_eq!(config.initial_lattice_capacity, DEFAULT_LATTICE_CELLS);
+    }
+
+    #[test]
+    fn starting_vessels_allocate_the_initial_capacity() {
+        let cosmos = Cosmos::parse(">").unwrap();
+        let vessel = starting_vessel(&cosmos, &Config::default());
+        assert_eq!(vessel.data_lattice().len(), DEFAULT_LATTICE_CELLS);
+
+        let config = Config::default().with_initial_lattice_capacity(1000);
+        let mut vessel = starting_vessel(&cosmos, &config);
+        assert_eq!(vessel.data_lattice().len(), 1000);
+        vessel.entropy_at(999);
+        assert_eq!(vessel.lattice_growths(), 0);
+
+        // Preset cells take precedence over the capacity.
+        let config = config.with_initial_lattice(vec![1, 2]);
+        assert_eq!(starting_vessel(&cosmos, &config).data_lattice().len(), 2);
+    }
+
+    #[test]
+    fn parking_starts_are_refused_with_their_own_error() {
+        let error = run_source("P>+.", Config::default()).err();
+        assert_eq!(error, Some(SailError::ParkingStart));
+        assert_eq!(
+            error.map(|error| error.to_json()),
+            Some("{\"error\":\"parking_start\",\"status\":66}".to_string())
+        );
+    }
+
+    #[test]
+    fn only_stopping_and_exiting_with_zero_succeed() {
+        assert!(Termination::Stopped.is_success());
+        assert!(Termination::Exited(0).is_success());
+        let failures = [
+            Termination::Exited(1),
+            Termination::NoSignal(0, 0),
+            Termination::StuckOscillation,
+            Termination::LatticeLimit,
+            Termination::StepLimit,
+            Termination::Timeout,
+            Termination::OutputLimit,
+        ];
+        assert!(failures.iter().all(|termination| !termination.is_success()));
+
+        assert_eq!(Termination::Stopped.exit_code(), ExitCode::SUCCESS);
+        assert_eq!(Termination::Exited(7).exit_code(), ExitCode::from(7));
+        assert_eq!(Termination::LatticeLimit.exit_code(), ExitCode::from(71));
+    }
+
+    #[test]
+    fn sandboxed_runs_stop_at_a_limit() {
+        let config = Config::sandboxed();
+        assert_eq!(config.max_steps, Some(10_000_000));
+        assert_eq!(config.timeout, Some(Duration::from_secs(5)));
+        assert_eq!(config.lattice_limit, Some(65_536));
+        assert_eq!(config.output_limit, Some(1 << 20));
+
+        let (termination, output) = run(">+.v\n^  <", "", Config::sandboxed());
+        assert_eq!(termination, Termination::OutputLimit);
+        assert!(output.len() > 1 << 20 && output.len() <= (1 << 20) + 4);
+        let (termination, _) = run(">+}v\n^  <", "", Config::sandboxed());
+        assert_eq!(termination, Termination::LatticeLimit);
+        let config = Config::sandboxed().with_max_steps(1_000);
+        assert_eq!(run(">+ v\n^  <", "", config).0, Termination::StepLimit);
+    }
+
+    #[test]
+    fn every_outcome_has_its_own_status() {
+        let terminations = [
+            (Termination::Stopped, 0),
+            (Termination::Exited(42), 42),
+            (Termination::NoSignal(1, 2), 67),
+            (Termination::StepLimit, 68),
+            (Termination::Timeout, 69),
+            (Termination::StuckOscillation, 70),
+            (Termination::LatticeLimit, 71),
+            (Termination::OutputLimit, 72),
+        ];
+        for (termination, status) in terminations {
+            assert_eq!(termination.status(), status, "{:?}", termination);
         }
+        let too_large = ParseError::TooLarge { cells: 4, limit: 2 };
+        assert_eq!(SailError::Parse(too_large).status(), 65);
+        assert_eq!(SailError::EmptyCosmos.status(), 66);
+        assert_eq!(SailError::NoInitialVelocityOrDirection.status(), 66);
     }
 
-    Termination::Stopped
+    #[test]
+    fn a_source_of_blank_lines_is_an_empty_cosmos() {
+        let error = run_source("\n\n\n", Config::default()).err();
+        assert_eq!(error, Some(SailError::EmptyCosmos));
+    }
 }
@@ -0,0 +1,94 @@
+use crate::models::{Cosmos, Rune};
+
+/// Paths drawn for a run keep at most this many cells, which bounds the
+/// image a long or endless run produces.
+pub const MAX_PATH_POINTS: usize = 100_000;
+
+// Side length of one cell in the image, in pixels.
+const CELL_SIZE: usize = 20;
+
+/// Renders `cosmos` as an SVG image, one square per cell labelled with its
+/// Rune, and `path` as a polyline through the centers of the cells it lists.
+pub fn path_to_svg(path: &[(usize, usize)], cosmos: &Cosmos) -> String {
+    let width = cosmos.width() * CELL_SIZE;
+    let height = cosmos.height() * CELL_SIZE;
+    let mut svg = format!(
+        "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{}\" height=\"{}\" viewBox=\"0 0 {} {}\">\n",
+        width, height, width, height
+    );
+    svg.push_str("  <rect width=\"100%\" height=\"100%\" fill=\"white\" stroke=\"gray\"/>\n");
+
+    svg.push_str("  <g font-family=\"monospace\" font-size=\"14\" text-anchor=\"middle\" dominant-baseline=\"central\">\n");
+    for y in 0..cosmos.height() {
+        for x in 0..cosmos.width() {
+            let rune = cosmos.get(x, y);
+            if rune == Rune::Void {
+                continue;
+            }
+            let (center_x, center_y) = center(x, y);
+            svg.push_str(&format!(
+                "    <text x=\"{}\" y=\"{}\">{}</text>\n",
+                center_x,
+                center_y,
+                escape(rune.to_char())
+            ));
+        }
+    }
+    svg.push_str("  </g>\n");
+
+    let points: Vec<String> = path
+        .iter()
+        .map(|&(x, y)| {
+            let (center_x, center_y) = center(x, y);
+            format!("{},{}", center_x, center_y)
+        })
+        .collect();
+    svg.push_str(&format!(
+        "  <polyline points=\"{}\" fill=\"none\" stroke=\"red\" stroke-width=\"2\" stroke-linejoin=\"round\" stroke-opacity=\"0.6\"/>\n",
+        points.join(" ")
+    ));
+    svg.push_str("</svg>\n");
+    svg
+}
+
+/// The pixel coordinates of the center of cell `(x, y)`.
+fn center(x: usize, y: usize) -> (usize, usize) {
+    (x * CELL_SIZE + CELL_SIZE / 2, y * CELL_SIZE + CELL_SIZE / 2)
+}
+
+/// A Rune glyph as SVG text; several glyphs are XML markup characters.
+fn escape(glyph: char) -> String {
+    match glyph {
+        '<' => "&lt;".to_string(),
+        '>' => "&gt;".to_string(),
+        '&' => "&amp;".to_string(),
+        '"' => "&quot;".to_string(),
+        _ => glyph.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn path_to_svg_labels_cells_and_draws_the_path() {
+        let cosmos = Cosmos::parse(">&v\n  <").unwrap();
+        let svg = path_to_svg(&[(0, 0), (1, 0), (2, 0), (2, 1)], &cosmos);
+        assert!(
+            svg.starts_with("<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"60\" height=\"40\"")
+        );
+        assert!(svg.contains("<text x=\"10\" y=\"10\">&gt;</text>"));
+        assert!(svg.contains("<text x=\"30\" y=\"10\">&amp;</text>"));
+        assert!(svg.contains("<text x=\"50\" y=\"30\">&lt;</text>"));
+        assert_eq!(svg.matches("<text").count(), 4);
+        assert!(svg.contains("<polyline points=\"10,10 30,10 50,10 50,30\""));
+        assert!(svg.ends_with("</svg>\n"));
+    }
+
+    #[test]
+    fn escape_leaves_plain_glyphs_alone() {
+        assert_eq!(escape('"'), "&quot;");
+        assert_eq!(escape('+'), "+");
+    }
+}
@@ -0,0 +1,73 @@
+//! Helpers shared by the unit tests.
+use std::cell::RefCell;
+use std::io::{self, Cursor, Write};
+use std::rc::Rc;
+
+use crate::machine::Machine;
+use crate::models::Cosmos;
+use crate::sail::{Config, Termination, starting_vessel};
+
+/// A writer whose bytes stay readable after it is handed to a Machine.
+#[derive(Clone, Default)]
+pub(crate) struct SharedBuffer(Rc<RefCell<Vec<u8>>>);
+
+impl SharedBuffer {
+    pub(crate) fn contents(&self) -> Vec<u8> {
+        self.0.borrow().clone()
+    }
+}
+
+impl Write for SharedBuffer {
+    fn write(&mut self, bytes: &[u8]) -> io::Result<usize> {
+        self.0.borrow_mut().extend_from_slice(bytes);
+        Ok(bytes.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// A Machine for `source` reading `input`, with its output captured in the
+/// returned buffer.
+pub(crate) fn machine(source: &str, input: &str, config: Config) -> (Machine, SharedBuffer) {
+    let cosmos = Cosmos::parse(source).expect("test program parses");
+    let vessel = starting_vessel(&cosmos, &config);
+    let mut machine = Machine::new(cosmos, vessel, config).expect("test program can start");
+    machine.set_input(Cursor::new(input.as_bytes().to_vec()));
+    let output = SharedBuffer::default();
+    machine.set_output(output.clone());
+    (machine, output)
+}
+
+/// Runs `source` to the end, returning how it ended and what it printed.
+pub(crate) fn run(source: &str, input: &str, config: Config) -> (Termination, Vec<u8>) {
+    let (mut machine, output) = machine(source, input, config);
+    let report = machine.run();
+    (report.termination(), output.contents())
+}
+
+/// A writer that records how many bytes had been written at each flush.
+#[derive(Clone, Default)]
+pub(crate) struct FlushLog {
+    written: Rc<RefCell<usize>>,
+    flushes: Rc<RefCell<Vec<usize>>>,
+}
+
+impl FlushLog {
+    pub(crate) fn flushes(&self) -> Vec<usize> {
+        self.flushes.borrow().clone()
+    }
+}
+
+impl Write for FlushLog {
+    fn write(&mut self, bytes: &[u8]) -> io::Result<usize> {
+        *self.written.borrow_mut() += bytes.len();
+        Ok(bytes.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.flushes.borrow_mut().push(*self.written.borrow());
+        Ok(())
+    }
+}